@@ -0,0 +1,325 @@
+use crate::config::{Alerts, Condition, Config, Host, Rule};
+use crate::notify::{self, Notification, Urgency};
+use crate::status::{HostState, Poller};
+use awc::Client;
+use chrono::{Local, TimeZone};
+use chrono_humanize::{Accuracy, HumanTime, Tense};
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::RwLock;
+
+const CHECK_INTERVAL: Duration = Duration::from_secs(10);
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(5);
+/// How many fired alerts the history page keeps.
+const ALERT_HISTORY: usize = 500;
+
+#[derive(Serialize, Clone)]
+pub struct Alert {
+    pub id: u64,
+    pub rule: String,
+    pub host: String,
+    pub condition: Condition,
+    /// Unix time the condition started holding.
+    pub since: i64,
+    pub fired_at: i64,
+    pub resolved_at: Option<i64>,
+    /// Silenced alerts are recorded but not delivered.
+    pub silenced: bool,
+    /// The outcome of each notification and webhook call.
+    pub deliveries: Vec<String>,
+}
+
+impl Alert {
+    pub fn message(&self) -> String {
+        format!(
+            "{} has been {} since {}",
+            self.host,
+            self.condition.describe(),
+            ago(self.since)
+        )
+    }
+
+    pub fn fired(&self) -> String {
+        ago(self.fired_at)
+    }
+
+    pub fn resolved(&self) -> Option<String> {
+        self.resolved_at.map(ago)
+    }
+}
+
+/// Suppresses delivery of matching alerts until `until`. A missing rule or
+/// host matches any.
+#[derive(Serialize, Clone)]
+pub struct Silence {
+    pub id: u64,
+    pub rule: Option<String>,
+    pub host: Option<String>,
+    pub until: i64,
+}
+
+impl Silence {
+    fn matches(&self, rule: &str, host: &str, now: i64) -> bool {
+        now < self.until
+            && self.rule.as_deref().is_none_or(|r| r == rule)
+            && self.host.as_deref().is_none_or(|h| h == host)
+    }
+
+    pub fn expires(&self) -> String {
+        HumanTime::from(timestamp(self.until)).to_text_en(Accuracy::Rough, Tense::Future)
+    }
+}
+
+#[derive(Default)]
+struct AlertState {
+    next_id: u64,
+    /// When each (rule, host) condition was first seen holding.
+    pending: HashMap<(String, String), i64>,
+    /// The alert currently firing for each (rule, host).
+    firing: HashMap<(String, String), u64>,
+    silences: Vec<Silence>,
+    history: VecDeque<Alert>,
+}
+
+impl AlertState {
+    fn alert_mut(&mut self, id: u64) -> Option<&mut Alert> {
+        self.history.iter_mut().find(|alert| alert.id == id)
+    }
+}
+
+/// Evaluates the configured alert rules against the status cache.
+#[derive(Clone)]
+pub struct Alerter {
+    config: Arc<RwLock<Config>>,
+    poller: Poller,
+    state: Arc<Mutex<AlertState>>,
+}
+
+impl Alerter {
+    /// Must be called from within the actix system, like [`Poller::start`].
+    pub fn start(config: Arc<RwLock<Config>>, poller: Poller) -> Self {
+        let alerter = Self {
+            config,
+            poller,
+            state: Arc::default(),
+        };
+
+        actix_web::rt::spawn(alerter.clone().run());
+
+        alerter
+    }
+
+    /// Alerts that haven't resolved yet, newest first.
+    pub fn firing(&self) -> Vec<Alert> {
+        let state = self.state.lock().unwrap();
+        state
+            .history
+            .iter()
+            .rev()
+            .filter(|alert| alert.resolved_at.is_none())
+            .cloned()
+            .collect()
+    }
+
+    /// Every alert kept, newest first.
+    pub fn history(&self) -> Vec<Alert> {
+        self.state
+            .lock()
+            .unwrap()
+            .history
+            .iter()
+            .rev()
+            .cloned()
+            .collect()
+    }
+
+    pub fn silences(&self) -> Vec<Silence> {
+        let now = Local::now().timestamp();
+        let mut state = self.state.lock().unwrap();
+        state.silences.retain(|silence| silence.until > now);
+        state.silences.clone()
+    }
+
+    pub fn silence(&self, rule: Option<String>, host: Option<String>, until: i64) -> u64 {
+        let mut state = self.state.lock().unwrap();
+        state.next_id += 1;
+        let id = state.next_id;
+        state.silences.push(Silence {
+            id,
+            rule,
+            host,
+            until,
+        });
+        id
+    }
+
+    pub fn unsilence(&self, id: u64) -> bool {
+        let mut state = self.state.lock().unwrap();
+        let before = state.silences.len();
+        state.silences.retain(|silence| silence.id != id);
+        state.silences.len() != before
+    }
+
+    async fn run(self) {
+        let mut interval = actix_web::rt::time::interval(CHECK_INTERVAL);
+        loop {
+            interval.tick().await;
+            self.check().await;
+        }
+    }
+
+    async fn check(&self) {
+        let (alerts, hosts) = {
+            let config = self.config.read().await;
+            (config.alerts.clone(), config.hosts.clone())
+        };
+
+        let mut fired = Vec::new();
+        for rule in &alerts.rules {
+            let watched = hosts
+                .keys()
+                .filter(|name| rule.hosts.is_empty() || rule.hosts.contains(name));
+
+            for name in watched {
+                let host_state = self.poller.get(name).await;
+                if let Some(alert) = self.evaluate(rule, name, &host_state) {
+                    fired.push((rule, alert));
+                }
+            }
+        }
+
+        for (rule, alert) in fired {
+            let deliveries = deliver(&alerts, &hosts, rule, &alert).await;
+            if let Some(alert) = self.state.lock().unwrap().alert_mut(alert.id) {
+                alert.deliveries = deliveries;
+            }
+        }
+    }
+
+    /// Updates the rule's state for one host and returns the alert to deliver
+    /// if it just fired.
+    fn evaluate(&self, rule: &Rule, host: &str, host_state: &HostState) -> Option<Alert> {
+        let now = Local::now().timestamp();
+        let key = (rule.name.clone(), host.to_string());
+        let mut state = self.state.lock().unwrap();
+
+        if !holds(rule.condition, host_state) {
+            state.pending.remove(&key);
+            if let Some(id) = state.firing.remove(&key)
+                && let Some(alert) = state.alert_mut(id)
+            {
+                alert.resolved_at = Some(now);
+            }
+            return None;
+        }
+
+        // Hosts that went offline before the dashboard noticed count from
+        // when the poller first failed to reach them.
+        let observed = match rule.condition {
+            Condition::Offline => host_state.offline_since.map(|t| t.timestamp()),
+            _ => None,
+        };
+        let since = *state
+            .pending
+            .entry(key.clone())
+            .or_insert(observed.unwrap_or(now));
+        if now - since < rule.after as i64 || state.firing.contains_key(&key) {
+            return None;
+        }
+
+        state.next_id += 1;
+        let alert = Alert {
+            id: state.next_id,
+            rule: rule.name.clone(),
+            host: host.to_string(),
+            condition: rule.condition,
+            since,
+            fired_at: now,
+            resolved_at: None,
+            silenced: state
+                .silences
+                .iter()
+                .any(|silence| silence.matches(&rule.name, host, now)),
+            deliveries: Vec::new(),
+        };
+
+        state.firing.insert(key, alert.id);
+        state.history.push_back(alert.clone());
+        if state.history.len() > ALERT_HISTORY {
+            state.history.pop_front();
+        }
+
+        (!alert.silenced).then_some(alert)
+    }
+}
+
+fn holds(condition: Condition, host_state: &HostState) -> bool {
+    // Hosts that haven't been polled yet are unknown rather than offline.
+    if host_state.last_contact.is_none() && host_state.offline_since.is_none() {
+        return false;
+    }
+
+    let status = host_state
+        .status
+        .as_ref()
+        .filter(|_| host_state.failures == 0);
+    match (condition, status) {
+        (Condition::Offline, status) => status.is_none(),
+        (_, None) => false,
+        (Condition::IdleUnlocked, Some(status)) => status.state() == "idle",
+        (Condition::Inhibited, Some(status)) => status.inhibited,
+    }
+}
+
+async fn deliver(
+    alerts: &Alerts,
+    hosts: &HashMap<String, Host>,
+    rule: &Rule,
+    alert: &Alert,
+) -> Vec<String> {
+    let mut deliveries = Vec::new();
+
+    if let Some(target) = rule.notify.as_ref().or(alerts.notify.as_ref()) {
+        let result = match hosts.get(target) {
+            Some(host) => {
+                let notification = Notification {
+                    summary: format!("Alert: {}", alert.rule),
+                    body: alert.message(),
+                    urgency: Urgency::Critical,
+                    timeout: -1,
+                };
+                notify::send(host, &notification).await
+            }
+            None => Err("no such host".to_string()),
+        };
+        deliveries.push(match result {
+            Ok(id) => format!("Notified {target} (#{id})"),
+            Err(e) => format!("Notifying {target} failed: {e}"),
+        });
+    }
+
+    if let Some(url) = rule.webhook.as_ref().or(alerts.webhook.as_ref()) {
+        let response = Client::default()
+            .post(url)
+            .timeout(DELIVERY_TIMEOUT)
+            .send_json(alert)
+            .await;
+        deliveries.push(match response {
+            Ok(res) if res.status().is_success() => format!("Called {url}"),
+            Ok(res) => format!("{url} returned {}", res.status()),
+            Err(e) => format!("Calling {url} failed: {e}"),
+        });
+    }
+
+    deliveries
+}
+
+fn timestamp(secs: i64) -> chrono::DateTime<Local> {
+    Local.timestamp_opt(secs, 0).single().unwrap_or_default()
+}
+
+fn ago(secs: i64) -> String {
+    HumanTime::from(timestamp(secs)).to_text_en(Accuracy::Rough, Tense::Past)
+}
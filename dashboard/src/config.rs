@@ -1,11 +1,148 @@
 use serde::{Deserialize, Deserializer, Serialize};
+use serde_yaml::{Mapping, Value};
+use std::io::{self, Write};
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
 use std::{collections::HashMap, env};
 
+/// Config file given on the command line, which wins over every other
+/// location.
+static PATH: OnceLock<PathBuf> = OnceLock::new();
+
 #[derive(Deserialize, Default, Debug)]
 pub struct Config {
     #[serde(deserialize_with = "deserialize_password")]
     pub password: String,
     pub hosts: HashMap<String, Host>,
+    /// Where host state transitions are recorded. Defaults to
+    /// `$XDG_STATE_HOME/mox/moxapi/history.jsonl`.
+    #[serde(default)]
+    pub history_file: Option<std::path::PathBuf>,
+    #[serde(default)]
+    pub alerts: Alerts,
+    /// The config file and every password, key and TLS file it references, as
+    /// absolute paths.
+    #[serde(skip)]
+    pub files: Vec<PathBuf>,
+    /// The key file each host's `api_key` was read from, if any.
+    #[serde(skip)]
+    pub key_files: HashMap<String, PathBuf>,
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    /// None of the config locations has a file.
+    NotFound,
+    Read {
+        path: PathBuf,
+        source: io::Error,
+    },
+    /// Invalid YAML, a value of the wrong type, or an unreadable password or
+    /// key file. The location is 1-based.
+    Parse {
+        path: PathBuf,
+        line: Option<usize>,
+        column: Option<usize>,
+        message: String,
+    },
+}
+
+impl ConfigError {
+    fn parse(path: &Path, e: serde_yaml::Error) -> Self {
+        let location = e.location();
+        let mut message = e.to_string();
+        // serde_yaml appends the location we report separately.
+        if let Some(at) = message.find(" at line ") {
+            message.truncate(at);
+        }
+
+        Self::Parse {
+            path: path.to_path_buf(),
+            line: location.as_ref().map(|l| l.line()),
+            column: location.as_ref().map(|l| l.column()),
+            message,
+        }
+    }
+}
+
+impl std::fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NotFound => write!(
+                f,
+                "No config file found. Pass one as an argument, set MOXAPI_CONFIG, or create ~/.config/mox/moxapi/config.yaml or /etc/moxapi/config.yaml"
+            ),
+            Self::Read { path, source } => write!(f, "{}: {source}", path.display()),
+            Self::Parse {
+                path,
+                line,
+                column,
+                message,
+            } => {
+                write!(f, "{}", path.display())?;
+                if let Some(line) = line {
+                    write!(f, ":{line}")?;
+                }
+                if let Some(column) = column {
+                    write!(f, ":{column}")?;
+                }
+                write!(f, ": {message}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Read { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Deserialize, Default, Debug, Clone)]
+pub struct Alerts {
+    #[serde(default)]
+    pub rules: Vec<Rule>,
+    /// Host that gets a desktop notification when a rule doesn't name one.
+    pub notify: Option<String>,
+    /// URL that gets every fired alert as JSON when a rule doesn't name one.
+    pub webhook: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct Rule {
+    pub name: String,
+    pub condition: Condition,
+    /// Seconds the condition has to hold before the rule fires.
+    #[serde(default)]
+    pub after: u64,
+    /// Hosts the rule watches; every host when empty.
+    #[serde(default)]
+    pub hosts: Vec<String>,
+    pub notify: Option<String>,
+    pub webhook: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Condition {
+    Offline,
+    /// Screensaver or logind idle without the session being locked.
+    IdleUnlocked,
+    Inhibited,
+}
+
+impl Condition {
+    pub fn describe(self) -> &'static str {
+        match self {
+            Self::Offline => "offline",
+            Self::IdleUnlocked => "idle and unlocked",
+            Self::Inhibited => "inhibiting idle",
+        }
+    }
 }
 
 fn deserialize_password<'de, D>(deserializer: D) -> Result<String, D::Error>
@@ -16,23 +153,213 @@ where
     if std::path::Path::new(&s).exists() {
         std::fs::read_to_string(&s)
             .map(|v| v.trim().to_string())
-            .map_err(serde::de::Error::custom)
+            .map_err(|e| serde::de::Error::custom(format!("can't read {s}: {e}")))
     } else {
         Ok(s)
     }
 }
 
 impl Config {
-    pub fn load() -> Option<Self> {
-        let path = Self::config_path()?;
-        let content = std::fs::read_to_string(path).ok()?;
+    /// Every group some host is in, sorted.
+    pub fn groups(&self) -> Vec<String> {
+        let mut groups: Vec<String> = self
+            .hosts
+            .values()
+            .flat_map(|host| host.groups.iter().cloned())
+            .collect();
+        groups.sort();
+        groups.dedup();
+        groups
+    }
+
+    /// The hosts `target` names: a single host, every host in a group, or
+    /// every host for `all`.
+    pub fn resolve_target(&self, target: &str) -> Vec<(&String, &Host)> {
+        if let Some((name, host)) = self.hosts.get_key_value(target) {
+            return vec![(name, host)];
+        }
+
+        self.hosts
+            .iter()
+            .filter(|(_, host)| target == "all" || host.groups.iter().any(|g| g == target))
+            .collect()
+    }
+
+    pub fn load() -> Result<Self, ConfigError> {
+        let path = Self::config_path().ok_or(ConfigError::NotFound)?;
+        let content = std::fs::read_to_string(&path).map_err(|source| ConfigError::Read {
+            path: path.clone(),
+            source,
+        })?;
 
-        serde_yaml::from_str(&content).ok()
+        let mut config: Self =
+            serde_yaml::from_str(&content).map_err(|e| ConfigError::parse(&path, e))?;
+        (config.files, config.key_files) = referenced_files(&path, &content);
+        Ok(config)
     }
 
-    fn config_path() -> Option<std::path::PathBuf> {
-        if let Some(arg_path) = env::args().nth(1) {
-            return Some(std::path::PathBuf::from(arg_path));
+    /// Use `path` instead of searching for the config file. Only the first
+    /// call has an effect.
+    pub fn set_path(path: PathBuf) {
+        _ = PATH.set(path);
+    }
+
+    /// Problems that don't stop the config from loading but are probably
+    /// mistakes.
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+
+        if self.password.is_empty() {
+            problems.push("password is empty, so nobody can log in".to_string());
+        }
+
+        let mut names: Vec<&String> = self.hosts.keys().collect();
+        names.sort();
+        for name in names {
+            let host = &self.hosts[name];
+            if let Err(e) = normalize_url(&host.ip) {
+                problems.push(format!("hosts.{name}.ip: {e}"));
+            }
+            let client_cert = host
+                .tls
+                .as_ref()
+                .is_some_and(|tls| tls.client_cert.is_some());
+            if host.api_key.is_empty() && !client_cert {
+                problems.push(format!("hosts.{name}.api_key is empty"));
+            } else if !self.key_files.contains_key(name) && host.api_key.contains('/') {
+                problems.push(format!(
+                    "hosts.{name}.api_key looks like a path but no such file exists, so it is used as the key itself"
+                ));
+            }
+
+            if let Some(tls) = &host.tls {
+                if !host.ip.starts_with("https://") {
+                    problems.push(format!("hosts.{name}.tls is only used for https:// URLs"));
+                }
+                problems.extend(tls_problems(name, tls));
+            }
+        }
+
+        let alerts = &self.alerts;
+        let notify_targets = alerts
+            .notify
+            .iter()
+            .map(|target| ("alerts.notify".to_string(), target));
+        let rule_targets = alerts.rules.iter().flat_map(|rule| {
+            rule.notify
+                .iter()
+                .chain(&rule.hosts)
+                .map(move |target| (format!("alerts rule {}", rule.name), target))
+        });
+        for (place, target) in notify_targets.chain(rule_targets) {
+            if !self.hosts.contains_key(target) {
+                problems.push(format!("{place} names unknown host {target}"));
+            }
+        }
+
+        problems
+    }
+
+    /// Adds `name` to the config file or updates it, leaving the rest of the
+    /// file as it was. `api_key` should be a key file path; `None` keeps the
+    /// host's current key.
+    pub fn save_host(
+        name: &str,
+        ip: &str,
+        api_key: Option<&Path>,
+        groups: &[String],
+    ) -> io::Result<()> {
+        Self::edit_hosts(|hosts| {
+            let key = Value::from(name);
+            if !hosts.get(&key).is_some_and(Value::is_mapping) {
+                hosts.insert(key.clone(), Value::Mapping(Mapping::new()));
+            }
+            let Some(Value::Mapping(host)) = hosts.get_mut(&key) else {
+                unreachable!();
+            };
+
+            host.insert("ip".into(), ip.into());
+            if let Some(api_key) = api_key {
+                host.insert(
+                    "api_key".into(),
+                    Value::from(api_key.to_string_lossy().into_owned()),
+                );
+            }
+            if groups.is_empty() {
+                host.remove("groups");
+            } else {
+                host.insert("groups".into(), groups.into());
+            }
+            Ok(())
+        })
+    }
+
+    pub fn remove_host(name: &str) -> io::Result<()> {
+        Self::edit_hosts(|hosts| {
+            hosts.remove(name);
+            Ok(())
+        })
+    }
+
+    /// Stores `key` in a file next to the config file, readable only by us,
+    /// and returns its path for `api_key`.
+    pub fn write_key_file(name: &str, key: &str) -> io::Result<PathBuf> {
+        let path = Self::config_path()
+            .and_then(|p| p.parent().map(|dir| dir.join("keys").join(name)))
+            .ok_or_else(|| io::Error::other("No config file to store keys next to"))?;
+        std::fs::create_dir_all(path.parent().unwrap_or(Path::new(".")))?;
+
+        write_atomic(&path, key.as_bytes(), 0o600)?;
+        Ok(path)
+    }
+
+    /// Whether `path` is in the config file's directory, the only place the
+    /// host form may read key files from. Anywhere else, anyone who can log in
+    /// could have any file the dashboard can read sent to a URL of their
+    /// choosing.
+    pub fn in_config_dir(path: &Path) -> bool {
+        let dir = Self::config_path()
+            .and_then(|p| std::path::absolute(p).ok())
+            .and_then(|p| p.parent()?.canonicalize().ok());
+        dir.zip(path.canonicalize().ok())
+            .is_some_and(|(dir, path)| path.starts_with(dir))
+    }
+
+    /// Lets `f` change the `hosts` mapping and writes it back. Only the
+    /// `hosts` section is rewritten, so comments elsewhere in the file are
+    /// kept, but those inside it are lost.
+    fn edit_hosts(f: impl FnOnce(&mut Mapping) -> io::Result<()>) -> io::Result<()> {
+        let path = Self::config_path().ok_or_else(|| io::Error::other("No config file found"))?;
+        let content = std::fs::read_to_string(&path)?;
+        let mut root: Value = serde_yaml::from_str(&content).map_err(io::Error::other)?;
+
+        let root_map = root
+            .as_mapping_mut()
+            .ok_or_else(|| io::Error::other("Config file is not a mapping"))?;
+        if !root_map.get("hosts").is_some_and(Value::is_mapping) {
+            root_map.insert("hosts".into(), Value::Mapping(Mapping::new()));
+        }
+        let Some(Value::Mapping(hosts)) = root_map.get_mut("hosts") else {
+            unreachable!();
+        };
+        f(hosts)?;
+        let hosts = hosts.clone();
+
+        // Fall back to rewriting the whole file if the section couldn't be
+        // swapped in cleanly, e.g. for a flow-style config.
+        let content = match splice_hosts(&content, hosts)
+            .filter(|new| serde_yaml::from_str::<Value>(new).is_ok_and(|new| new == root))
+        {
+            Some(content) => content,
+            None => serde_yaml::to_string(&root).map_err(io::Error::other)?,
+        };
+        let mode = std::fs::metadata(&path)?.permissions().mode();
+        write_atomic(&path, content.as_bytes(), mode)
+    }
+
+    pub fn config_path() -> Option<std::path::PathBuf> {
+        if let Some(arg_path) = PATH.get() {
+            return Some(arg_path.clone());
         }
         if let Ok(env_path) = env::var("MOXAPI_CONFIG") {
             return Some(std::path::PathBuf::from(env_path));
@@ -52,11 +379,178 @@ impl Config {
     }
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub struct Host {
     pub ip: String,
-    #[serde(deserialize_with = "deserialize_api_key")]
+    /// May be left out when the node knows the dashboard by its client
+    /// certificate.
+    #[serde(default, deserialize_with = "deserialize_api_key", skip_serializing)]
     pub api_key: String,
+    /// Groups the host can be filtered and acted on by, e.g. `lab`.
+    #[serde(default)]
+    pub groups: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tls: Option<HostTls>,
+}
+
+/// How to check an `https://` node's certificate and prove who we are.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct HostTls {
+    /// PEM CA the node's certificate is signed by, instead of the usual
+    /// public roots.
+    pub ca: Option<PathBuf>,
+    /// Hex SHA-256 of the node's certificate, which is then trusted whoever
+    /// signed it.
+    pub pin: Option<String>,
+    /// PEM certificate and key to present to nodes that check clients.
+    pub client_cert: Option<PathBuf>,
+    #[serde(skip_serializing)]
+    pub client_key: Option<PathBuf>,
+}
+
+fn tls_problems(name: &str, tls: &HostTls) -> Vec<String> {
+    let mut problems = Vec::new();
+
+    let files = [
+        ("ca", &tls.ca),
+        ("client_cert", &tls.client_cert),
+        ("client_key", &tls.client_key),
+    ];
+    for (field, file) in files {
+        if let Some(file) = file
+            && !file.is_file()
+        {
+            problems.push(format!(
+                "hosts.{name}.tls.{field}: {} doesn't exist",
+                file.display()
+            ));
+        }
+    }
+    if tls.client_cert.is_some() != tls.client_key.is_some() {
+        problems.push(format!(
+            "hosts.{name}.tls needs both client_cert and client_key"
+        ));
+    }
+    if let Some(pin) = &tls.pin {
+        let pin = crate::tls::normalize_fingerprint(pin);
+        if pin.len() != 64 || !pin.bytes().all(|b| b.is_ascii_hexdigit()) {
+            problems.push(format!(
+                "hosts.{name}.tls.pin should be the hex SHA-256 of the certificate"
+            ));
+        }
+    }
+
+    problems
+}
+
+/// The config file plus the password and key files it points at, which the
+/// deserializers read in place of the value, the TLS files hosts use, and
+/// the key file of each host.
+fn referenced_files(path: &Path, content: &str) -> (Vec<PathBuf>, HashMap<String, PathBuf>) {
+    let existing = |value: Option<&Value>| {
+        value
+            .and_then(Value::as_str)
+            .map(Path::new)
+            .filter(|file| file.exists())
+            .and_then(|file| std::path::absolute(file).ok())
+    };
+
+    let mut files: Vec<PathBuf> = std::path::absolute(path).into_iter().collect();
+    let mut key_files = HashMap::new();
+
+    if let Ok(root) = serde_yaml::from_str::<Value>(content) {
+        files.extend(existing(root.get("password")));
+
+        let hosts = root.get("hosts").and_then(Value::as_mapping);
+        for (name, host) in hosts.into_iter().flatten() {
+            if let (Some(name), Some(file)) = (name.as_str(), existing(host.get("api_key"))) {
+                files.push(file.clone());
+                key_files.insert(name.to_string(), file);
+            }
+            if let Some(tls) = host.get("tls") {
+                for field in ["ca", "client_cert", "client_key"] {
+                    files.extend(existing(tls.get(field)));
+                }
+            }
+        }
+    }
+
+    (files, key_files)
+}
+
+/// Replaces the top-level `hosts:` block of `content`, leaving every other
+/// line as it was.
+fn splice_hosts(content: &str, hosts: Mapping) -> Option<String> {
+    let mut section = Mapping::new();
+    section.insert("hosts".into(), Value::Mapping(hosts));
+    let section = serde_yaml::to_string(&section).ok()?;
+
+    let lines: Vec<&str> = content.split_inclusive('\n').collect();
+    let Some(start) = lines.iter().position(|line| line.starts_with("hosts:")) else {
+        let mut content = content.to_string();
+        if !content.is_empty() && !content.ends_with('\n') {
+            content.push('\n');
+        }
+        content.push_str(&section);
+        return Some(content);
+    };
+
+    // The section ends at the next top-level key. Comments and blank lines
+    // right before that key go with it.
+    let top_level = |line: &&str| !line.starts_with(char::is_whitespace) && !line.starts_with('#');
+    let loose = |line: &str| line.trim().is_empty() || line.starts_with('#');
+    let mut end = lines[start + 1..]
+        .iter()
+        .position(top_level)
+        .map_or(lines.len(), |i| start + 1 + i);
+    while end > start + 1 && loose(lines[end - 1]) {
+        end -= 1;
+    }
+
+    Some(
+        [
+            &lines[..start].concat(),
+            section.as_str(),
+            &lines[end..].concat(),
+        ]
+        .concat(),
+    )
+}
+
+/// Checks a node URL is a bare `http(s)://host[:port]` and normalizes it.
+pub fn normalize_url(ip: &str) -> Result<String, String> {
+    let ip = ip.trim().trim_end_matches('/');
+    let uri: awc::http::Uri = ip.parse().map_err(|_| format!("{ip} is not a URL"))?;
+
+    if !matches!(uri.scheme_str(), Some("http" | "https")) {
+        return Err("The URL has to start with http:// or https://".to_string());
+    }
+    if uri.host().is_none_or(str::is_empty) {
+        return Err(format!("{ip} has no host name"));
+    }
+    if !matches!(uri.path(), "" | "/") || uri.query().is_some() {
+        return Err("Leave the path off the URL".to_string());
+    }
+
+    Ok(ip.to_string())
+}
+
+/// Writes `contents` to a temporary file and renames it over `path`, so
+/// readers never see a partial file.
+fn write_atomic(path: &Path, contents: &[u8], mode: u32) -> io::Result<()> {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+
+    let mut file = std::fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(mode)
+        .open(&tmp)?;
+    file.write_all(contents)?;
+    file.sync_all()?;
+
+    std::fs::rename(&tmp, path)
 }
 
 fn deserialize_api_key<'de, D>(deserializer: D) -> Result<String, D::Error>
@@ -67,7 +561,7 @@ where
     if std::path::Path::new(&s).exists() {
         std::fs::read_to_string(&s)
             .map(|v| v.trim().to_string())
-            .map_err(serde::de::Error::custom)
+            .map_err(|e| serde::de::Error::custom(format!("can't read {s}: {e}")))
     } else {
         Ok(s)
     }
@@ -0,0 +1,140 @@
+use crate::config::{Config, Host};
+use crate::{sign, tls};
+use actix_web::rt::task::JoinHandle;
+use futures::StreamExt;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{RwLock, broadcast};
+
+/// How long to wait before reconnecting to a host whose stream dropped.
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+/// Nodes send a keep-alive every 15s, so a silent stream is a dead one.
+const STREAM_TIMEOUT: Duration = Duration::from_secs(45);
+/// How often the host list is compared against the running subscriptions.
+const RECONCILE_INTERVAL: Duration = Duration::from_secs(5);
+
+#[derive(Serialize, Clone, Debug)]
+pub struct HostEvent {
+    pub host: String,
+    /// The node's event as sent, or a `connected`/`disconnected` event
+    /// generated here.
+    pub event: serde_json::Value,
+}
+
+/// Keeps one `/events` subscription per configured host and republishes
+/// everything they send.
+#[derive(Clone)]
+pub struct Events {
+    tx: broadcast::Sender<HostEvent>,
+}
+
+impl Events {
+    /// Must be called from within the actix system, since the subscriptions
+    /// run on it.
+    pub fn start(config: Arc<RwLock<Config>>) -> Self {
+        let (tx, _) = broadcast::channel(256);
+        let events = Self { tx };
+
+        let supervisor = events.clone();
+        actix_web::rt::spawn(async move { supervisor.supervise(config).await });
+
+        events
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<HostEvent> {
+        self.tx.subscribe()
+    }
+
+    async fn supervise(self, config: Arc<RwLock<Config>>) {
+        let mut running: HashMap<String, (Host, JoinHandle<()>)> = HashMap::new();
+
+        loop {
+            {
+                let config = config.read().await;
+
+                running.retain(|name, (host, task)| {
+                    let keep = config.hosts.get(name) == Some(host);
+                    if !keep {
+                        task.abort();
+                    }
+                    keep
+                });
+
+                for (name, host) in &config.hosts {
+                    if running.contains_key(name) {
+                        continue;
+                    }
+
+                    let task =
+                        actix_web::rt::spawn(self.clone().follow(name.clone(), host.clone()));
+                    running.insert(name.clone(), (host.clone(), task));
+                }
+            }
+
+            actix_web::rt::time::sleep(RECONCILE_INTERVAL).await;
+        }
+    }
+
+    async fn follow(self, name: String, host: Host) {
+        loop {
+            let reason = match self.stream(&name, &host).await {
+                Ok(()) => "stream ended".to_string(),
+                Err(e) => e,
+            };
+
+            self.publish(
+                &name,
+                serde_json::json!({ "event": "disconnected", "reason": reason }),
+            );
+            actix_web::rt::time::sleep(RECONNECT_DELAY).await;
+        }
+    }
+
+    async fn stream(&self, name: &str, host: &Host) -> Result<(), String> {
+        let request = tls::client(host)?.get(format!("{}/events", host.ip));
+        let mut response = sign::sign(request, &host.api_key, b"")
+            .insert_header(("Accept", "text/event-stream"))
+            .send()
+            .await
+            .map_err(|e| e.to_string())?;
+
+        if !response.status().is_success() {
+            return Err(format!("status {}", response.status()));
+        }
+
+        self.publish(name, serde_json::json!({ "event": "connected" }));
+
+        let mut buffer = Vec::new();
+        loop {
+            let chunk = match actix_web::rt::time::timeout(STREAM_TIMEOUT, response.next()).await {
+                Ok(Some(chunk)) => chunk.map_err(|e| e.to_string())?,
+                Ok(None) => return Ok(()),
+                Err(_) => return Err("timed out".to_string()),
+            };
+            buffer.extend_from_slice(&chunk);
+
+            // Events are separated by a blank line; only `data:` matters since
+            // the node repeats the event name in the JSON.
+            while let Some(end) = buffer.windows(2).position(|w| w == b"\n\n") {
+                let message: Vec<u8> = buffer.drain(..end + 2).collect();
+                let message = String::from_utf8_lossy(&message);
+
+                for data in message.lines().filter_map(|l| l.strip_prefix("data:")) {
+                    if let Ok(event) = serde_json::from_str(data.trim()) {
+                        self.publish(name, event);
+                    }
+                }
+            }
+        }
+    }
+
+    fn publish(&self, host: &str, event: serde_json::Value) {
+        // Nobody watching the dashboard is fine.
+        _ = self.tx.send(HostEvent {
+            host: host.to_string(),
+            event,
+        });
+    }
+}
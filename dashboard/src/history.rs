@@ -0,0 +1,216 @@
+use chrono::{Datelike, Days, Local, NaiveDate, TimeZone};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, RwLock};
+
+pub const STATES: [&str; 4] = ["online", "idle", "locked", "offline"];
+
+/// One line of the history file.
+#[derive(Serialize, Deserialize)]
+struct Record {
+    host: String,
+    state: String,
+    /// Unix time in seconds.
+    at: i64,
+}
+
+#[derive(Deserialize, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum Period {
+    Daily,
+    Weekly,
+}
+
+impl Period {
+    pub fn default_count(self) -> usize {
+        match self {
+            Self::Daily => 7,
+            Self::Weekly => 4,
+        }
+    }
+
+    /// The start of the bucket containing `day`.
+    fn start_of(self, day: NaiveDate) -> NaiveDate {
+        match self {
+            Self::Daily => day,
+            Self::Weekly => day - Days::new(day.weekday().num_days_from_monday().into()),
+        }
+    }
+
+    fn next(self, start: NaiveDate) -> NaiveDate {
+        match self {
+            Self::Daily => start + Days::new(1),
+            Self::Weekly => start + Days::new(7),
+        }
+    }
+}
+
+/// Time spent in each state during one day or week, in seconds.
+#[derive(Serialize, Default, Clone)]
+pub struct Summary {
+    /// First day of the bucket, as `YYYY-MM-DD`.
+    pub start: String,
+    pub length: u64,
+    pub active: u64,
+    pub idle: u64,
+    pub locked: u64,
+    pub offline: u64,
+    /// How many times the host went from another state to locked.
+    pub locks: u32,
+    /// Share of the observed time the host was reachable, in percent. `None`
+    /// when nothing was observed.
+    pub uptime: Option<f64>,
+}
+
+impl Summary {
+    fn add(&mut self, state: &str, seconds: u64) {
+        match state {
+            "online" => self.active += seconds,
+            "idle" => self.idle += seconds,
+            "locked" => self.locked += seconds,
+            _ => self.offline += seconds,
+        }
+    }
+
+    /// Percentage of the whole bucket, for chart widths.
+    pub fn percent(&self, seconds: u64) -> String {
+        format!("{:.2}", seconds as f64 * 100.0 / self.length.max(1) as f64)
+    }
+}
+
+/// Append-only log of state transitions per host.
+pub struct History {
+    file: Mutex<File>,
+    transitions: RwLock<HashMap<String, Vec<(i64, &'static str)>>>,
+}
+
+impl History {
+    pub fn open(path: &Path) -> std::io::Result<Self> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        let mut transitions: HashMap<String, Vec<(i64, &'static str)>> = HashMap::new();
+        if let Ok(file) = File::open(path) {
+            for line in BufReader::new(file).lines() {
+                // A torn last line from a crash shouldn't lose everything else.
+                let Ok(record) = serde_json::from_str::<Record>(&line?) else {
+                    continue;
+                };
+                if let Some(state) = STATES.iter().find(|s| **s == record.state) {
+                    transitions
+                        .entry(record.host)
+                        .or_default()
+                        .push((record.at, state));
+                }
+            }
+        }
+
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+
+        Ok(Self {
+            file: Mutex::new(file),
+            transitions: RwLock::new(transitions),
+        })
+    }
+
+    pub fn default_path() -> PathBuf {
+        dirs::state_dir()
+            .or_else(dirs::data_local_dir)
+            .unwrap_or_default()
+            .join("mox/moxapi/history.jsonl")
+    }
+
+    /// Records `state` for `host` unless it's already the host's last state.
+    pub fn record(&self, host: &str, state: &'static str) -> std::io::Result<()> {
+        let mut transitions = self.transitions.write().unwrap();
+        let host_transitions = transitions.entry(host.to_string()).or_default();
+        if host_transitions.last().is_some_and(|(_, s)| *s == state) {
+            return Ok(());
+        }
+
+        let at = Local::now().timestamp();
+        let mut line = serde_json::to_string(&Record {
+            host: host.to_string(),
+            state: state.to_string(),
+            at,
+        })?;
+        line.push('\n');
+        self.file.lock().unwrap().write_all(line.as_bytes())?;
+
+        host_transitions.push((at, state));
+        Ok(())
+    }
+
+    /// The last `count` buckets of `period` for `host`, oldest first. The
+    /// current bucket only counts time up to now.
+    pub fn summaries(&self, host: &str, period: Period, count: usize) -> Vec<Summary> {
+        let now = Local::now();
+        let transitions = self.transitions.read().unwrap();
+        let host_transitions = transitions.get(host).map(Vec::as_slice).unwrap_or_default();
+
+        let mut start = period.start_of(now.date_naive());
+        let mut buckets = Vec::with_capacity(count);
+        for _ in 0..count {
+            buckets.push(start);
+            start = period.start_of(start - Days::new(1));
+        }
+        buckets.reverse();
+
+        buckets
+            .into_iter()
+            .map(|start| {
+                let from = local_midnight(start).min(now.timestamp());
+                let to = local_midnight(period.next(start)).min(now.timestamp());
+                summarize(host_transitions, start, from, to, now.timestamp())
+            })
+            .collect()
+    }
+}
+
+fn local_midnight(day: NaiveDate) -> i64 {
+    let midnight = day.and_hms_opt(0, 0, 0).unwrap_or_default();
+    Local
+        .from_local_datetime(&midnight)
+        .earliest()
+        .map(|t| t.timestamp())
+        .unwrap_or_else(|| midnight.and_utc().timestamp())
+}
+
+fn summarize(
+    transitions: &[(i64, &'static str)],
+    start: NaiveDate,
+    from: i64,
+    to: i64,
+    now: i64,
+) -> Summary {
+    let mut summary = Summary {
+        start: start.format("%Y-%m-%d").to_string(),
+        length: (to - from).max(0) as u64,
+        ..Default::default()
+    };
+
+    for (i, (at, state)) in transitions.iter().enumerate() {
+        let until = transitions.get(i + 1).map_or(now, |(next, _)| *next);
+        let overlap = until.min(to) - (*at).max(from);
+        if overlap > 0 {
+            summary.add(state, overlap as u64);
+        }
+
+        // Consecutive records never repeat a state, so every locked record
+        // after the first observation is a lock.
+        if *state == "locked" && i > 0 && (from..to).contains(at) {
+            summary.locks += 1;
+        }
+    }
+
+    let observed = summary.active + summary.idle + summary.locked + summary.offline;
+    if observed > 0 {
+        summary.uptime = Some((observed - summary.offline) as f64 * 100.0 / observed as f64);
+    }
+
+    summary
+}
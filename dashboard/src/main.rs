@@ -1,17 +1,25 @@
+mod alerts;
 mod config;
+mod events;
+mod history;
+mod notify;
+mod reload;
+mod sign;
+mod status;
+mod tls;
 
 use actix_session::{Session, SessionMiddleware, storage::CookieSessionStore};
 use actix_web::cookie::Key;
-use actix_web::{App, HttpResponse, HttpServer, Responder, get, post, web};
+use actix_web::{App, HttpRequest, HttpResponse, HttpServer, delete, get, post, web};
 use actix_web::{http::header, middleware::Logger};
 use askama::Template;
-use awc::Client;
 use chrono_humanize::{Accuracy, Tense};
+use clap::{Parser, Subcommand};
 use futures::future;
 use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
-use std::time::Duration;
-use tokio::sync::RwLock;
+use tokio::sync::{RwLock, broadcast};
 
 #[derive(Template)]
 #[template(path = "dashboard.html")]
@@ -20,6 +28,7 @@ struct DashboardTemplate {
     ip: String,
     status: &'static str,
     last_seen: String,
+    history: Vec<history::Summary>,
 }
 
 #[derive(Debug, Clone)]
@@ -33,56 +42,67 @@ pub struct HostWithStatus {
 #[template(path = "main_dashboard.html")]
 pub struct MainDashboardTemplate {
     pub hosts: Vec<HostWithStatus>,
+    pub groups: Vec<String>,
+    pub group: Option<String>,
+    /// Set while the config on disk is invalid and an older one is in use.
+    pub config_error: Option<String>,
+}
+
+#[derive(Template)]
+#[template(path = "host.html")]
+struct HostTabTemplate {
+    host: HostWithStatus,
+    status: String,
+}
+
+fn logged_in(session: &Session) -> bool {
+    session
+        .get::<bool>("logged_in")
+        .unwrap_or(Some(false))
+        .unwrap_or(false)
+}
+
+async fn host_with_status(
+    poller: &status::Poller,
+    hostname: &str,
+    host: &config::Host,
+) -> HostWithStatus {
+    HostWithStatus {
+        hostname: hostname.to_string(),
+        ip: host.ip.clone(),
+        status: poller.get(hostname).await.state().to_string(),
+    }
+}
+
+#[derive(Deserialize)]
+struct GroupQuery {
+    group: Option<String>,
 }
 
 #[get("/")]
 async fn index(
     session: Session,
+    query: web::Query<GroupQuery>,
     data: web::Data<Arc<RwLock<config::Config>>>,
+    poller: web::Data<status::Poller>,
+    reloader: web::Data<reload::Reloader>,
 ) -> Result<HttpResponse, actix_web::Error> {
-    if session
-        .get::<bool>("logged_in")
-        .unwrap_or(Some(false))
-        .unwrap_or(false)
-    {
+    if logged_in(&session) {
         let config = data.read().await;
+        let group = query.into_inner().group;
 
-        let futures: Vec<_> = config
-            .hosts
-            .iter()
-            .map(|(hostname, host)| async {
-                let response = Client::default()
-                    .get(format!("{}/status", host.ip))
-                    .insert_header(("Authorization", host.api_key.clone()))
-                    .timeout(Duration::from_secs(1))
-                    .send()
-                    .await;
-                let status = match response {
-                    Ok(mut res) => res.json::<Status>().await.unwrap_or_default(),
-                    Err(_) => {
-                        return HostWithStatus {
-                            hostname: hostname.clone(),
-                            ip: host.ip.clone(),
-                            status: "offline".to_string(),
-                        };
-                    }
-                };
-                let status = match status.active {
-                    true => "idle",
-                    false => "online",
-                }
-                .to_string();
-
-                HostWithStatus {
-                    hostname: hostname.clone(),
-                    ip: host.ip.clone(),
-                    status,
-                }
-            })
-            .collect();
-
-        let hosts: Vec<HostWithStatus> = future::join_all(futures).await;
-        let template = MainDashboardTemplate { hosts };
+        let mut hosts = Vec::with_capacity(config.hosts.len());
+        for (hostname, host) in &config.hosts {
+            if group.as_ref().is_none_or(|g| host.groups.contains(g)) {
+                hosts.push(host_with_status(&poller, hostname, host).await);
+            }
+        }
+        let template = MainDashboardTemplate {
+            hosts,
+            groups: config.groups(),
+            group,
+            config_error: reloader.status().error.map(|(_, e)| e),
+        };
         Ok(HttpResponse::Ok()
             .content_type("text/html")
             .body(template.render().unwrap()))
@@ -93,53 +113,144 @@ async fn index(
     }
 }
 
-#[derive(Deserialize, Serialize, Default)]
-struct Status {
-    active: bool,
-    active_time: u32,
-    inhibited: bool,
+#[get("/host-tab/{hostname}")]
+async fn host_tab(
+    session: Session,
+    path: web::Path<String>,
+    data: web::Data<Arc<RwLock<config::Config>>>,
+    poller: web::Data<status::Poller>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if !logged_in(&session) {
+        return Ok(HttpResponse::Unauthorized().finish());
+    }
+
+    let hostname = path.into_inner();
+    let config = data.read().await;
+    let host = match config.hosts.get(&hostname) {
+        Some(h) => h,
+        None => return Ok(HttpResponse::NotFound().body("Host not found")),
+    };
+
+    let host = host_with_status(&poller, &hostname, host).await;
+    let template = HostTabTemplate {
+        status: host.status.clone(),
+        host,
+    };
+    Ok(HttpResponse::Ok().body(template.render().unwrap()))
+}
+
+/// Relays every host's events to the browser as Server-Sent Events, once the
+/// status cache has caught up with them.
+#[get("/events")]
+async fn get_events(
+    session: Session,
+    poller: web::Data<status::Poller>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if !logged_in(&session) {
+        return Ok(HttpResponse::Unauthorized().finish());
+    }
+
+    let stream = futures::stream::unfold(poller.subscribe(), |mut rx| async move {
+        loop {
+            match rx.recv().await {
+                Ok(event) => {
+                    let payload = format!("data: {}\n\n", serde_json::to_string(&event).ok()?);
+                    return Some((Ok::<_, actix_web::Error>(web::Bytes::from(payload)), rx));
+                }
+                Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(broadcast::error::RecvError::Closed) => return None,
+            }
+        }
+    });
+
+    Ok(HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header(("Cache-Control", "no-cache"))
+        .streaming(stream))
 }
 
 #[get("/status/{hostname}")]
 async fn get_status(
     path: web::Path<String>,
-    data: web::Data<Arc<RwLock<config::Config>>>,
+    poller: web::Data<status::Poller>,
 ) -> Result<HttpResponse, actix_web::Error> {
     let hostname = path.into_inner();
-    let config = data.read().await;
-    let host = config.hosts.get(&hostname).unwrap();
-    let response = Client::default()
-        .get(format!("{}/status", host.ip))
-        .insert_header(("Authorization", host.api_key.clone()))
-        .send()
-        .await;
-
-    Ok(HttpResponse::Ok().json(response.unwrap().json::<Status>().await.unwrap_or_default()))
+    match poller.get(&hostname).await.status {
+        Some(status) => Ok(HttpResponse::Ok().json(status)),
+        None => Ok(HttpResponse::NotFound().body("No status for host")),
+    }
 }
 
-#[derive(Template)]
-#[template(path = "add_host_form.html")]
-struct AddHostFormTemplate;
+#[derive(Template, Default)]
+#[template(path = "host_form.html")]
+struct HostFormTemplate {
+    name: String,
+    ip: String,
+    groups: String,
+    editing: bool,
+}
 
 #[get("/add-host-form")]
-async fn add_host_form() -> Result<HttpResponse, actix_web::Error> {
-    let template = AddHostFormTemplate;
+async fn add_host_form(session: Session) -> Result<HttpResponse, actix_web::Error> {
+    if !logged_in(&session) {
+        return Ok(HttpResponse::Unauthorized().finish());
+    }
+
+    let template = HostFormTemplate::default();
+    Ok(HttpResponse::Ok().body(template.render().unwrap()))
+}
+
+#[get("/edit-host-form/{hostname}")]
+async fn edit_host_form(
+    session: Session,
+    path: web::Path<String>,
+    data: web::Data<Arc<RwLock<config::Config>>>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if !logged_in(&session) {
+        return Ok(HttpResponse::Unauthorized().finish());
+    }
+
+    let hostname = path.into_inner();
+    let config = data.read().await;
+    let host = match config.hosts.get(&hostname) {
+        Some(h) => h,
+        None => return Ok(HttpResponse::NotFound().body("Host not found")),
+    };
+
+    let template = HostFormTemplate {
+        ip: host.ip.clone(),
+        groups: host.groups.join(", "),
+        name: hostname,
+        editing: true,
+    };
     Ok(HttpResponse::Ok().body(template.render().unwrap()))
 }
 
 #[get("/hosts")]
 async fn get_hosts(
+    session: Session,
     data: web::Data<Arc<RwLock<config::Config>>>,
 ) -> Result<HttpResponse, actix_web::Error> {
+    if !logged_in(&session) {
+        return Ok(HttpResponse::Unauthorized().finish());
+    }
+
     let config = data.read().await;
     Ok(HttpResponse::Ok().json(&config.hosts))
 }
 
 #[get("/dashboard/{hostname}")]
 async fn dashboard(
+    session: Session,
     path: web::Path<String>,
     data: web::Data<Arc<RwLock<config::Config>>>,
+    poller: web::Data<status::Poller>,
+    history: web::Data<Arc<history::History>>,
 ) -> Result<HttpResponse, actix_web::Error> {
+    if !logged_in(&session) {
+        return Ok(HttpResponse::Unauthorized().finish());
+    }
+
     let hostname = path.into_inner();
     let config = data.read().await;
     let host = match config.hosts.get(&hostname) {
@@ -147,65 +258,311 @@ async fn dashboard(
         None => return Ok(HttpResponse::NotFound().body("Host not found")),
     };
 
-    let response = Client::default()
-        .get(format!("{}/status", host.ip))
-        .insert_header(("Authorization", host.api_key.clone()))
-        .send()
-        .await;
-
-    let mut response = match response {
-        Ok(resp) => resp,
-        Err(_) => {
-            let template = DashboardTemplate {
-                ip: host.ip.clone(),
-                status: "offline",
-                hostname,
-                last_seen: "now".to_string(),
-            };
-            return Ok(HttpResponse::Ok().body(template.render().unwrap()));
+    let state = poller.get(&hostname).await;
+    let status = state.state();
+    let last_seen = match (&state.status, state.offline_since) {
+        (Some(s), None) => {
+            let dt = chrono::Local::now() - chrono::Duration::seconds(s.active_time as i64);
+            chrono_humanize::HumanTime::from(dt).to_text_en(Accuracy::Rough, Tense::Past)
         }
-    };
-
-    let status: Status = match response.json().await {
-        Ok(s) => s,
-        Err(_) => {
-            let template = DashboardTemplate {
-                ip: host.ip.clone(),
-                status: "offline",
-                hostname,
-                last_seen: "now".to_string(),
-            };
-            return Ok(HttpResponse::Ok().body(template.render().unwrap()));
-        }
-    };
-
-    let dt = chrono::Local::now() - chrono::Duration::seconds(status.active_time as i64);
-    let ht = chrono_humanize::HumanTime::from(dt);
-
-    let status = match status.active {
-        true => "idle",
-        false => "online",
+        (_, Some(since)) => format!(
+            "offline since {}",
+            chrono_humanize::HumanTime::from(since).to_text_en(Accuracy::Rough, Tense::Past)
+        ),
+        (None, None) => "never".to_string(),
     };
 
     let template = DashboardTemplate {
         ip: host.ip.clone(),
         status,
+        history: history.summaries(&hostname, history::Period::Daily, 7),
         hostname,
-        last_seen: ht.to_text_en(Accuracy::Rough, Tense::Past),
+        last_seen,
     };
     Ok(HttpResponse::Ok().body(template.render().unwrap()))
 }
 
+#[derive(Deserialize)]
+struct HistoryQuery {
+    count: Option<usize>,
+}
+
+/// Time spent active, idle, locked and offline per day or week, oldest first.
+#[get("/history/{hostname}/{period}")]
+async fn get_history(
+    session: Session,
+    path: web::Path<(String, history::Period)>,
+    query: web::Query<HistoryQuery>,
+    data: web::Data<Arc<RwLock<config::Config>>>,
+    history: web::Data<Arc<history::History>>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if !logged_in(&session) {
+        return Ok(HttpResponse::Unauthorized().finish());
+    }
+
+    let (hostname, period) = path.into_inner();
+    if !data.read().await.hosts.contains_key(&hostname) {
+        return Ok(HttpResponse::NotFound().body("Host not found"));
+    }
+
+    let count = query.count.unwrap_or(period.default_count()).clamp(1, 366);
+    Ok(HttpResponse::Ok().json(history.summaries(&hostname, period, count)))
+}
+
+#[derive(Template)]
+#[template(source = "{{ message }}", ext = "html")]
+struct MessageTemplate<'a> {
+    message: &'a str,
+}
+
+/// Shows `message` in the host form's error slot instead of replacing the
+/// form.
+fn host_form_error(message: &str) -> HttpResponse {
+    HttpResponse::Ok()
+        .insert_header(("HX-Retarget", "#host-form-error"))
+        .insert_header(("HX-Reswap", "innerHTML"))
+        .body(MessageTemplate { message }.render().unwrap())
+}
+
+/// Reloads the page so every view picks up the changed host list.
+fn refresh() -> HttpResponse {
+    HttpResponse::Ok()
+        .insert_header(("HX-Refresh", "true"))
+        .finish()
+}
+
+const PROBE_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(3);
+
+#[derive(Deserialize)]
+struct HostForm {
+    #[serde(default)]
+    name: String,
+    #[serde(default)]
+    ip: String,
+    /// A key, a path to a key file, or empty to keep the current key.
+    #[serde(default)]
+    api_key: String,
+    /// Comma-separated.
+    #[serde(default)]
+    groups: String,
+}
+
+impl HostForm {
+    fn groups(&self) -> Vec<String> {
+        self.groups
+            .split(',')
+            .map(str::trim)
+            .filter(|g| !g.is_empty())
+            .map(str::to_string)
+            .collect()
+    }
+}
+
+/// Names end up in key file paths, so ones made only of dots are refused.
+fn valid_host_name(name: &str) -> bool {
+    !name.is_empty()
+        && name != "all"
+        && !name.chars().all(|c| c == '.')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+}
+
+/// Validates the form, checks the node answers with the key, and writes the
+/// host to the config file. `current` is the host being edited.
+async fn save_host(
+    name: &str,
+    form: &HostForm,
+    current: Option<config::Host>,
+) -> Result<config::Host, String> {
+    let ip = config::normalize_url(&form.ip)?;
+    let tls = current.as_ref().and_then(|current| current.tls.clone());
+
+    // Keys given directly are moved into a key file so the config only ever
+    // references them, the same way `api_key` paths are read.
+    let key_input = form.api_key.trim();
+    let (api_key, key_file) = if key_input.is_empty() {
+        let current = current.ok_or("An API key is required")?;
+        (current.api_key, None)
+    } else if Path::new(key_input).is_file() {
+        if !config::Config::in_config_dir(Path::new(key_input)) {
+            return Err("Key files have to be next to the config file".to_string());
+        }
+        let key = std::fs::read_to_string(key_input)
+            .map_err(|e| format!("Couldn't read {key_input}: {e}"))?;
+        (key.trim().to_string(), Some(PathBuf::from(key_input)))
+    } else {
+        (key_input.to_string(), None)
+    };
+
+    let host = config::Host {
+        ip,
+        api_key,
+        groups: form.groups(),
+        tls,
+    };
+    status::probe(&host, PROBE_TIMEOUT).await?;
+
+    let key_file = match key_file {
+        Some(path) => Some(path),
+        None if !key_input.is_empty() => Some(
+            config::Config::write_key_file(name, &host.api_key)
+                .map_err(|e| format!("Couldn't write the key file: {e}"))?,
+        ),
+        None => None,
+    };
+
+    config::Config::save_host(name, &host.ip, key_file.as_deref(), &host.groups)
+        .map_err(|e| format!("Couldn't save the config: {e}"))?;
+
+    Ok(host)
+}
+
 #[post("/add-host")]
-async fn add_host(_form: web::Form<std::collections::HashMap<String, String>>) -> impl Responder {
-    HttpResponse::Ok().body("<div style='text-align:center;padding:2rem 0;'>Adding hosts is disabled. Edit config/hosts.yaml.</div>")
+async fn add_host(
+    session: Session,
+    form: web::Form<HostForm>,
+    data: web::Data<Arc<RwLock<config::Config>>>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if !logged_in(&session) {
+        return Ok(HttpResponse::Unauthorized().finish());
+    }
+
+    let name = form.name.trim();
+    if !valid_host_name(name) {
+        return Ok(host_form_error(
+            "Host names may only contain letters, digits, '-', '_' and '.'",
+        ));
+    }
+    if data.read().await.hosts.contains_key(name) {
+        return Ok(host_form_error(&format!("{name} already exists")));
+    }
+
+    match save_host(name, &form, None).await {
+        Ok(host) => {
+            data.write().await.hosts.insert(name.to_string(), host);
+            Ok(refresh())
+        }
+        Err(e) => Ok(host_form_error(&e)),
+    }
+}
+
+#[post("/hosts/{hostname}")]
+async fn edit_host(
+    session: Session,
+    path: web::Path<String>,
+    form: web::Form<HostForm>,
+    data: web::Data<Arc<RwLock<config::Config>>>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if !logged_in(&session) {
+        return Ok(HttpResponse::Unauthorized().finish());
+    }
+
+    let hostname = path.into_inner();
+    let Some(current) = data.read().await.hosts.get(&hostname).cloned() else {
+        return Ok(HttpResponse::NotFound().body("Host not found"));
+    };
+
+    match save_host(&hostname, &form, Some(current)).await {
+        Ok(host) => {
+            data.write().await.hosts.insert(hostname, host);
+            Ok(refresh())
+        }
+        Err(e) => Ok(host_form_error(&e)),
+    }
+}
+
+#[delete("/hosts/{hostname}")]
+async fn remove_host(
+    session: Session,
+    path: web::Path<String>,
+    data: web::Data<Arc<RwLock<config::Config>>>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if !logged_in(&session) {
+        return Ok(HttpResponse::Unauthorized().finish());
+    }
+
+    let hostname = path.into_inner();
+    if !data.read().await.hosts.contains_key(&hostname) {
+        return Ok(HttpResponse::NotFound().body("Host not found"));
+    }
+
+    config::Config::remove_host(&hostname).map_err(|e| {
+        actix_web::error::ErrorInternalServerError(format!("Couldn't save the config: {e}"))
+    })?;
+    data.write().await.hosts.remove(&hostname);
+    Ok(refresh())
+}
+
+#[derive(Deserialize)]
+struct ActionQuery {
+    #[serde(default)]
+    confirm: bool,
+}
+
+fn action_endpoint(action: &str) -> Option<&'static str> {
+    match action {
+        "lock" => Some("/idle/lock"),
+        "unlock" => Some("/idle/unlock"),
+        "simulate-activity" => Some("/idle/simulate_user_activity"),
+        "inhibit" => Some("/idle/inhibit"),
+        "uninhibit" => Some("/idle/uninhibit"),
+        "suspend" => Some("/power/suspend"),
+        "hibernate" => Some("/power/hibernate"),
+        "hybrid-sleep" => Some("/power/hybrid-sleep"),
+        "reboot" => Some("/power/reboot"),
+        "poweroff" => Some("/power/power-off"),
+        _ => None,
+    }
+}
+
+/// Checks that `action` exists and power actions were confirmed, returning
+/// the node endpoint to call.
+fn check_action(action: &str, confirm: bool) -> Result<&'static str, HttpResponse> {
+    let endpoint =
+        action_endpoint(action).ok_or_else(|| HttpResponse::BadRequest().body("Unknown action"))?;
+    if endpoint.starts_with("/power/") && !confirm {
+        return Err(HttpResponse::BadRequest().body(format!("Confirm {action} first")));
+    }
+    Ok(endpoint)
+}
+
+const ACTION_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
+async fn send_action(host: &config::Host, action: &str, endpoint: &str) -> Result<(), String> {
+    let url = if endpoint.starts_with("/power/") {
+        format!("{}{endpoint}?confirm=true", host.ip)
+    } else {
+        format!("{}{endpoint}", host.ip)
+    };
+
+    let request = tls::client(host)?.post(url).timeout(ACTION_TIMEOUT);
+    let resp = sign::sign(request, &host.api_key, b"")
+        .send()
+        .await
+        .map_err(|e| format!("Failed to send {action} command: {e}"))?;
+
+    if resp.status().is_success() {
+        Ok(())
+    } else {
+        Err(format!(
+            "Failed to send {action} command. Status: {}",
+            resp.status()
+        ))
+    }
 }
 
 #[post("/action/{hostname}/{action}")]
 async fn host_action(
+    session: Session,
     path: web::Path<(String, String)>,
+    query: web::Query<ActionQuery>,
     data: web::Data<Arc<RwLock<config::Config>>>,
 ) -> Result<HttpResponse, actix_web::Error> {
+    if !logged_in(&session) {
+        return Ok(HttpResponse::Unauthorized().finish());
+    }
+
     let (hostname, action) = path.into_inner();
     let config = data.read().await;
 
@@ -214,43 +571,315 @@ async fn host_action(
         .get(&hostname)
         .ok_or_else(|| actix_web::error::ErrorNotFound("Host not found"))?;
 
-    let endpoint = match action.as_str() {
-        "lock" => "/idle/lock",
-        "unlock" => "/idle/unlock",
-        "simulate-activity" => "/idle/simulate_user_activity",
-        "inhibit" => "/idle/inhibit",
-        "uninhibit" => "/idle/uninhibit",
-        _ => return Ok(HttpResponse::BadRequest().body("Unknown action")),
+    let endpoint = match check_action(&action, query.confirm) {
+        Ok(endpoint) => endpoint,
+        Err(response) => return Ok(response),
     };
 
-    let resp = Client::default()
-        .post(format!("{}{endpoint}", host.ip))
-        .insert_header(("Authorization", host.api_key.clone()))
-        .send()
+    send_action(host, &action, endpoint)
         .await
-        .map_err(|e| {
-            actix_web::error::ErrorInternalServerError(format!(
-                "Failed to send {action} command: {e}"
-            ))
-        })?;
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    Ok(HttpResponse::Ok().body(format!("{action} command sent successfully!")))
+}
 
-    if resp.status().is_success() {
-        Ok(HttpResponse::Ok().body(format!("{action} command sent successfully!")))
+#[derive(Serialize)]
+struct HostResult {
+    host: String,
+    ok: bool,
+    message: String,
+    /// The notification id on the host, for notifications.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    id: Option<u32>,
+}
+
+#[derive(Template)]
+#[template(path = "host_results.html")]
+struct HostResultsTemplate {
+    results: Vec<HostResult>,
+}
+
+/// Per-host results as an HTML fragment for htmx, JSON otherwise.
+fn host_results(req: &HttpRequest, mut results: Vec<HostResult>) -> HttpResponse {
+    results.sort_by(|a, b| a.host.cmp(&b.host));
+    if req.headers().contains_key("HX-Request") {
+        let template = HostResultsTemplate { results };
+        HttpResponse::Ok().body(template.render().unwrap())
     } else {
-        Err(actix_web::error::ErrorInternalServerError(format!(
-            "Failed to send {action} command. Status: {}",
-            resp.status()
-        )))
+        HttpResponse::Ok().json(results)
+    }
+}
+
+/// Runs `action` on every host in the group at once.
+#[post("/action/group/{group}/{action}")]
+async fn group_action(
+    req: HttpRequest,
+    session: Session,
+    path: web::Path<(String, String)>,
+    query: web::Query<ActionQuery>,
+    data: web::Data<Arc<RwLock<config::Config>>>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if !logged_in(&session) {
+        return Ok(HttpResponse::Unauthorized().finish());
+    }
+
+    let (group, action) = path.into_inner();
+    let config = data.read().await;
+
+    let endpoint = match check_action(&action, query.confirm) {
+        Ok(endpoint) => endpoint,
+        Err(response) => return Ok(response),
+    };
+
+    let members: Vec<_> = config
+        .hosts
+        .iter()
+        .filter(|(_, host)| host.groups.contains(&group))
+        .collect();
+    if members.is_empty() {
+        return Ok(HttpResponse::NotFound().body("Group not found"));
+    }
+
+    let results = future::join_all(members.into_iter().map(|(hostname, host)| {
+        let action = &action;
+        async move {
+            let result = send_action(host, action, endpoint).await;
+            HostResult {
+                host: hostname.clone(),
+                ok: result.is_ok(),
+                message: result
+                    .err()
+                    .unwrap_or_else(|| format!("{action} command sent successfully!")),
+                id: None,
+            }
+        }
+    }))
+    .await;
+
+    Ok(host_results(&req, results))
+}
+
+#[derive(Template)]
+#[template(path = "notify_form.html")]
+struct NotifyFormTemplate {
+    target: String,
+    groups: Vec<String>,
+    hosts: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct TargetQuery {
+    #[serde(default)]
+    target: String,
+}
+
+#[get("/notify-form")]
+async fn notify_form(
+    session: Session,
+    query: web::Query<TargetQuery>,
+    data: web::Data<Arc<RwLock<config::Config>>>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if !logged_in(&session) {
+        return Ok(HttpResponse::Unauthorized().finish());
+    }
+
+    let config = data.read().await;
+    let mut hosts: Vec<String> = config.hosts.keys().cloned().collect();
+    hosts.sort();
+
+    let template = NotifyFormTemplate {
+        target: query.into_inner().target,
+        groups: config.groups(),
+        hosts,
+    };
+    Ok(HttpResponse::Ok().body(template.render().unwrap()))
+}
+
+/// Sends a notification to a host, every host in a group, or `all` of them
+/// at once.
+#[post("/notify/{target}")]
+async fn notify_target(
+    req: HttpRequest,
+    session: Session,
+    path: web::Path<String>,
+    body: web::Either<web::Json<notify::Notification>, web::Form<notify::Notification>>,
+    data: web::Data<Arc<RwLock<config::Config>>>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if !logged_in(&session) {
+        return Ok(HttpResponse::Unauthorized().finish());
+    }
+
+    let target = path.into_inner();
+    let notification = match body {
+        web::Either::Left(json) => json.into_inner(),
+        web::Either::Right(form) => form.into_inner(),
+    };
+    if notification.summary.trim().is_empty() {
+        return Ok(HttpResponse::BadRequest().body("A summary is required"));
+    }
+
+    let config = data.read().await;
+    let hosts = config.resolve_target(&target);
+    if hosts.is_empty() {
+        return Ok(HttpResponse::NotFound().body("No host or group by that name"));
+    }
+
+    let notification = &notification;
+    let results = future::join_all(hosts.into_iter().map(|(hostname, host)| async move {
+        let result = notify::send(host, notification).await;
+        HostResult {
+            host: hostname.clone(),
+            ok: result.is_ok(),
+            message: match &result {
+                Ok(id) => format!("Delivered as notification {id}"),
+                Err(e) => format!("Failed to send notification: {e}"),
+            },
+            id: result.ok(),
+        }
+    }))
+    .await;
+
+    Ok(host_results(&req, results))
+}
+
+#[derive(Template)]
+#[template(path = "alerts.html")]
+struct AlertsTemplate {
+    firing: Vec<alerts::Alert>,
+    silences: Vec<alerts::Silence>,
+    history: Vec<alerts::Alert>,
+    rules: Vec<String>,
+    hosts: Vec<String>,
+}
+
+#[get("/alerts")]
+async fn alerts_page(
+    session: Session,
+    data: web::Data<Arc<RwLock<config::Config>>>,
+    alerter: web::Data<alerts::Alerter>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if !logged_in(&session) {
+        return Ok(HttpResponse::Found()
+            .append_header((header::LOCATION, "/login"))
+            .finish());
+    }
+
+    let config = data.read().await;
+    let mut hosts: Vec<String> = config.hosts.keys().cloned().collect();
+    hosts.sort();
+
+    let template = AlertsTemplate {
+        firing: alerter.firing(),
+        silences: alerter.silences(),
+        history: alerter.history(),
+        rules: config.alerts.rules.iter().map(|r| r.name.clone()).collect(),
+        hosts,
+    };
+    Ok(HttpResponse::Ok()
+        .content_type("text/html")
+        .body(template.render().unwrap()))
+}
+
+#[derive(Deserialize)]
+struct SilenceForm {
+    #[serde(default)]
+    rule: String,
+    #[serde(default)]
+    host: String,
+    minutes: u64,
+}
+
+#[post("/alerts/silences")]
+async fn add_silence(
+    session: Session,
+    form: web::Form<SilenceForm>,
+    alerter: web::Data<alerts::Alerter>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if !logged_in(&session) {
+        return Ok(HttpResponse::Unauthorized().finish());
+    }
+
+    let form = form.into_inner();
+    let until = chrono::Local::now().timestamp() + form.minutes.min(60 * 24 * 365) as i64 * 60;
+    alerter.silence(
+        Some(form.rule).filter(|r| !r.is_empty()),
+        Some(form.host).filter(|h| !h.is_empty()),
+        until,
+    );
+
+    Ok(HttpResponse::Found()
+        .append_header((header::LOCATION, "/alerts"))
+        .finish())
+}
+
+#[delete("/alerts/silences/{id}")]
+async fn remove_silence(
+    session: Session,
+    path: web::Path<u64>,
+    alerter: web::Data<alerts::Alerter>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if !logged_in(&session) {
+        return Ok(HttpResponse::Unauthorized().finish());
+    }
+
+    if alerter.unsilence(path.into_inner()) {
+        Ok(HttpResponse::Ok().finish())
+    } else {
+        Ok(HttpResponse::NotFound().body("Silence not found"))
     }
 }
 
 #[post("/reload-config")]
 async fn reload_config(
-    data: web::Data<Arc<RwLock<config::Config>>>,
+    session: Session,
+    reloader: web::Data<reload::Reloader>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if !logged_in(&session) {
+        return Ok(HttpResponse::Unauthorized().finish());
+    }
+
+    match reloader.reload().await {
+        Ok(()) => Ok(HttpResponse::Ok().body("Config reloaded.")),
+        Err(e) => {
+            Ok(HttpResponse::InternalServerError().body(format!("Kept the previous config. {e}")))
+        }
+    }
+}
+
+#[derive(Template)]
+#[template(path = "config_status.html")]
+struct ConfigStatusTemplate {
+    files: Vec<String>,
+    loaded: Option<String>,
+    error: Option<(String, String)>,
+}
+
+#[get("/config")]
+async fn config_status(
+    session: Session,
+    reloader: web::Data<reload::Reloader>,
 ) -> Result<HttpResponse, actix_web::Error> {
-    let mut config = data.write().await;
-    *config = config::Config::load().unwrap_or_default();
-    Ok(HttpResponse::Ok().body("Config reloaded."))
+    if !logged_in(&session) {
+        return Ok(HttpResponse::Found()
+            .append_header((header::LOCATION, "/login"))
+            .finish());
+    }
+
+    let status = reloader.status();
+    let ago = |t: chrono::DateTime<chrono::Local>| {
+        chrono_humanize::HumanTime::from(t).to_text_en(Accuracy::Rough, Tense::Past)
+    };
+    let template = ConfigStatusTemplate {
+        files: status
+            .files
+            .iter()
+            .map(|f| f.display().to_string())
+            .collect(),
+        loaded: status.loaded_at.map(ago),
+        error: status.error.map(|(at, e)| (ago(at), e)),
+    };
+    Ok(HttpResponse::Ok()
+        .content_type("text/html")
+        .body(template.render().unwrap()))
 }
 
 #[derive(Template)]
@@ -275,7 +904,8 @@ async fn login_post(
         let data = data.into_inner();
         let config = data.read().await;
 
-        if pass == &config.password {
+        // An empty password would let anyone in.
+        if !config.password.is_empty() && pass == &config.password {
             session.insert("logged_in", true).unwrap();
             return Ok(HttpResponse::Found()
                 .append_header((header::LOCATION, "/"))
@@ -287,14 +917,114 @@ async fn login_post(
     Ok(HttpResponse::Ok().body(template.render().unwrap()))
 }
 
+#[derive(Parser)]
+#[command(author, version, about, long_about = None)]
+struct Cli {
+    /// Config file to use instead of searching the usual locations
+    config: Option<PathBuf>,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Validate the config and the key files it points at, then exit
+    CheckConfig {
+        /// Also check each host answers with its API key
+        #[arg(long)]
+        probe: bool,
+    },
+}
+
+/// Prints what's wrong with the config, returning whether it's usable.
+async fn check_config(probe: bool) -> bool {
+    let config = match config::Config::load() {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("error: {e}");
+            return false;
+        }
+    };
+
+    if let Some(path) = config.files.first() {
+        println!("{}", path.display());
+    }
+
+    let problems = config.problems();
+    for problem in &problems {
+        eprintln!("error: {problem}");
+    }
+
+    let mut names: Vec<&String> = config.hosts.keys().collect();
+    names.sort();
+    let mut failed = 0;
+    for name in names {
+        let host = &config.hosts[name];
+        let key = match config.key_files.get(name) {
+            Some(file) => format!("key from {}", file.display()),
+            None if host.api_key.is_empty() => "client certificate".to_string(),
+            None => "inline key".to_string(),
+        };
+
+        if !probe {
+            println!("  {name}: {} ({key})", host.ip);
+            continue;
+        }
+        match status::probe(host, PROBE_TIMEOUT).await {
+            Ok(_) => println!("  {name}: {} ({key}) ok", host.ip),
+            Err(e) => {
+                failed += 1;
+                println!("  {name}: {} ({key}) failed: {e}", host.ip);
+            }
+        }
+    }
+
+    if failed > 0 {
+        eprintln!("error: {failed} host(s) didn't answer");
+    }
+    problems.is_empty() && failed == 0
+}
+
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
+    let cli = Cli::parse();
+    if let Some(path) = cli.config {
+        config::Config::set_path(path);
+    }
+    if let Some(Command::CheckConfig { probe }) = cli.command {
+        std::process::exit(if check_config(probe).await { 0 } else { 1 });
+    }
+
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+
     let secret = Key::generate();
-    let config = Arc::new(RwLock::new(config::Config::load().unwrap_or_default()));
+    let reloader = reload::Reloader::load().unwrap_or_else(|e| {
+        log::error!("{e}");
+        std::process::exit(1);
+    });
+    if let Err(e) = reloader.watch() {
+        log::warn!("Config changes won't be picked up automatically: {e}");
+    }
+    let config = reloader.config();
+    let history_file = config
+        .read()
+        .await
+        .history_file
+        .clone()
+        .unwrap_or_else(history::History::default_path);
+    let history = Arc::new(history::History::open(&history_file)?);
+    let events = events::Events::start(config.clone());
+    let poller = status::Poller::start(config.clone(), &events, history.clone());
+    let alerter = alerts::Alerter::start(config.clone(), poller.clone());
 
     HttpServer::new(move || {
         App::new()
             .app_data(web::Data::new(config.clone()))
+            .app_data(web::Data::new(reloader.clone()))
+            .app_data(web::Data::new(poller.clone()))
+            .app_data(web::Data::new(history.clone()))
+            .app_data(web::Data::new(alerter.clone()))
             .wrap(Logger::default())
             .wrap(
                 SessionMiddleware::builder(CookieSessionStore::default(), secret.clone())
@@ -304,13 +1034,26 @@ async fn main() -> std::io::Result<()> {
             .service(login_form)
             .service(login_post)
             .service(index)
+            .service(host_tab)
+            .service(get_events)
             .service(add_host_form)
             .service(add_host)
+            .service(edit_host_form)
+            .service(edit_host)
+            .service(remove_host)
             .service(get_hosts)
             .service(dashboard)
             .service(host_action)
+            .service(group_action)
+            .service(notify_form)
+            .service(notify_target)
             .service(get_status)
+            .service(get_history)
             .service(reload_config)
+            .service(config_status)
+            .service(alerts_page)
+            .service(add_silence)
+            .service(remove_silence)
             .service(actix_files::Files::new("/static", "./static").show_files_listing())
     })
     .bind(("0.0.0.0", 8080))?
@@ -0,0 +1,66 @@
+use crate::config::Host;
+use crate::{sign, tls};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+const SEND_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Deserialize, Serialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Urgency {
+    Low,
+    #[default]
+    Normal,
+    Critical,
+}
+
+/// A desktop notification as the dashboard forwards it to nodes.
+#[derive(Deserialize, Clone)]
+pub struct Notification {
+    pub summary: String,
+    #[serde(default)]
+    pub body: String,
+    #[serde(default)]
+    pub urgency: Urgency,
+    /// Milliseconds, or -1 to leave it to the notification server.
+    #[serde(default = "default_timeout")]
+    pub timeout: i32,
+}
+
+fn default_timeout() -> i32 {
+    -1
+}
+
+#[derive(Deserialize)]
+struct Sent {
+    id: u32,
+}
+
+/// Shows `notification` on `host` and returns its id there.
+pub async fn send(host: &Host, notification: &Notification) -> Result<u32, String> {
+    let body = serde_json::to_vec(&serde_json::json!({
+        "summary": notification.summary,
+        "body": notification.body,
+        "timeout": notification.timeout,
+        "hints": { "urgency": notification.urgency },
+    }))
+    .map_err(|e| e.to_string())?;
+    let request = tls::client(host)?
+        .post(format!("{}/notify", host.ip))
+        .timeout(SEND_TIMEOUT);
+    let mut response = sign::sign(request, &host.api_key, &body)
+        .insert_header(("Content-Type", "application/json"))
+        .send_body(body)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    if !response.status().is_success() {
+        return Err(format!("status {}", response.status()));
+    }
+
+    response
+        .json::<Sent>()
+        .await
+        .map(|sent| sent.id)
+        .map_err(|e| e.to_string())
+}
@@ -0,0 +1,138 @@
+use crate::config::{Config, ConfigError};
+use chrono::{DateTime, Local};
+use notify::{EventKind, RecursiveMode, Watcher};
+use std::collections::BTreeSet;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::{RwLock, mpsc};
+
+/// Editors and secret managers often write a file in several steps, so
+/// changes are collected for this long before reloading.
+const DEBOUNCE: Duration = Duration::from_millis(250);
+
+#[derive(Default, Clone)]
+pub struct ConfigStatus {
+    pub files: Vec<PathBuf>,
+    pub loaded_at: Option<DateTime<Local>>,
+    /// Why the last reload was rejected, if the current config is older than
+    /// the files on disk.
+    pub error: Option<(DateTime<Local>, String)>,
+}
+
+/// Swaps in a new config only once it has loaded completely.
+#[derive(Clone)]
+pub struct Reloader {
+    config: Arc<RwLock<Config>>,
+    status: Arc<Mutex<ConfigStatus>>,
+}
+
+impl Reloader {
+    /// Loads the config. There's nothing safe to fall back on if it's
+    /// invalid, as an empty config has an empty password.
+    pub fn load() -> Result<Self, ConfigError> {
+        let config = Config::load()?;
+        let reloader = Self {
+            config: Arc::default(),
+            status: Arc::default(),
+        };
+        reloader.loaded(&config);
+        *reloader.config.try_write().unwrap() = config;
+
+        Ok(reloader)
+    }
+
+    pub fn config(&self) -> Arc<RwLock<Config>> {
+        self.config.clone()
+    }
+
+    pub fn status(&self) -> ConfigStatus {
+        self.status.lock().unwrap().clone()
+    }
+
+    /// Re-reads the config, keeping the current one if the new one is invalid.
+    pub async fn reload(&self) -> Result<(), String> {
+        match Config::load() {
+            Ok(config) => {
+                self.loaded(&config);
+                *self.config.write().await = config;
+                crate::tls::clear_cache();
+                log::info!("Reloaded config");
+                Ok(())
+            }
+            Err(e) => {
+                let e = e.to_string();
+                self.failed(e.clone());
+                Err(e)
+            }
+        }
+    }
+
+    fn loaded(&self, config: &Config) {
+        for problem in config.problems() {
+            log::warn!("{problem}");
+        }
+
+        let mut status = self.status.lock().unwrap();
+        status.files = config.files.clone();
+        status.loaded_at = Some(Local::now());
+        status.error = None;
+    }
+
+    fn failed(&self, error: String) {
+        log::error!("Keeping the previous config: {error}");
+        self.status.lock().unwrap().error = Some((Local::now(), error));
+    }
+
+    /// Reloads whenever the config file or a key file it references changes.
+    /// Directories are watched rather than the files, since most tools
+    /// replace files instead of writing them in place.
+    pub fn watch(&self) -> notify::Result<()> {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let mut watcher =
+            notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+                if let Ok(event) = event
+                    && !matches!(event.kind, EventKind::Access(_))
+                {
+                    _ = tx.send(());
+                }
+            })?;
+
+        let mut watched = BTreeSet::new();
+        self.update_watches(&mut watcher, &mut watched);
+
+        let reloader = self.clone();
+        actix_web::rt::spawn(async move {
+            while rx.recv().await.is_some() {
+                actix_web::rt::time::sleep(DEBOUNCE).await;
+                while rx.try_recv().is_ok() {}
+
+                if reloader.reload().await.is_ok() {
+                    reloader.update_watches(&mut watcher, &mut watched);
+                }
+            }
+        });
+
+        Ok(())
+    }
+
+    fn update_watches(&self, watcher: &mut impl Watcher, watched: &mut BTreeSet<PathBuf>) {
+        let dirs: BTreeSet<PathBuf> = self
+            .status()
+            .files
+            .iter()
+            .filter_map(|file| file.parent().map(PathBuf::from))
+            .collect();
+
+        for dir in watched.difference(&dirs) {
+            _ = watcher.unwatch(dir);
+        }
+        for dir in dirs.difference(watched) {
+            if let Err(e) = watcher.watch(dir, RecursiveMode::NonRecursive) {
+                log::warn!("Failed to watch {}: {e}", dir.display());
+            }
+        }
+
+        *watched = dirs;
+    }
+}
@@ -0,0 +1,54 @@
+use awc::ClientRequest;
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+static COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// Adds a signature made with `api_key` over the request and `body`, which
+/// has to be exactly what's sent, in place of the key itself. A captured
+/// request can then only be replayed within the node's window, and only
+/// once. Without a key the request goes unsigned, for nodes that know us by
+/// our client certificate.
+pub fn sign(request: ClientRequest, api_key: &str, body: &[u8]) -> ClientRequest {
+    if api_key.is_empty() {
+        return request;
+    }
+
+    let path = request
+        .get_uri()
+        .path_and_query()
+        .map_or("/", |path| path.as_str())
+        .to_string();
+    let header = authorization(api_key, request.get_method().as_str(), &path, body);
+    request.insert_header(("Authorization", header))
+}
+
+fn authorization(api_key: &str, method: &str, path: &str, body: &[u8]) -> String {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    // Nonces only have to be unique, as the signature covers them.
+    let nonce = format!(
+        "{:x}{:x}",
+        now.as_nanos(),
+        COUNTER.fetch_add(1, Ordering::Relaxed)
+    );
+    let timestamp = now.as_secs();
+
+    let mut mac = Hmac::<Sha256>::new_from_slice(api_key.as_bytes())
+        .expect("HMAC accepts keys of any length");
+    mac.update(
+        format!(
+            "{method}\n{path}\n{}\n{timestamp}\n{nonce}",
+            hex::encode(Sha256::digest(body))
+        )
+        .as_bytes(),
+    );
+
+    format!(
+        "MoxAPI-HMAC-SHA256 ts={timestamp}, nonce={nonce}, sig={}",
+        hex::encode(mac.finalize().into_bytes())
+    )
+}
@@ -0,0 +1,201 @@
+use crate::config::{Config, Host};
+use crate::events::{Events, HostEvent};
+use crate::history::History;
+use crate::{sign, tls};
+use chrono::{DateTime, Local};
+use futures::future;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{RwLock, broadcast};
+
+/// How often every host is polled, independently of its events.
+const POLL_INTERVAL: Duration = Duration::from_secs(10);
+const POLL_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Deserialize, Serialize, Default, Clone)]
+pub struct Status {
+    pub active: bool,
+    pub active_time: u32,
+    pub inhibited: bool,
+    #[serde(default)]
+    pub session: Option<SessionStatus>,
+}
+
+#[derive(Deserialize, Serialize, Default, Clone)]
+pub struct SessionStatus {
+    pub locked: bool,
+    pub idle: bool,
+}
+
+impl Status {
+    /// Locked sessions win over the screensaver being active, which in turn
+    /// wins over logind's idle hint.
+    pub fn state(&self) -> &'static str {
+        match (&self.session, self.active) {
+            (Some(session), _) if session.locked => "locked",
+            (_, true) => "idle",
+            (Some(session), false) if session.idle => "idle",
+            _ => "online",
+        }
+    }
+}
+
+/// What the dashboard remembers about a host between polls.
+#[derive(Default, Clone)]
+pub struct HostState {
+    /// The last status the host answered with, kept while it's offline.
+    pub status: Option<Status>,
+    pub last_contact: Option<DateTime<Local>>,
+    /// Polls that failed since the last successful one.
+    pub failures: u32,
+    /// When the host stopped answering.
+    pub offline_since: Option<DateTime<Local>>,
+}
+
+impl HostState {
+    pub fn state(&self) -> &'static str {
+        match &self.status {
+            Some(status) if self.failures == 0 => status.state(),
+            _ => "offline",
+        }
+    }
+}
+
+/// Polls every configured host in the background and serves pages from the
+/// results, so slow hosts don't hold up page loads.
+#[derive(Clone)]
+pub struct Poller {
+    config: Arc<RwLock<Config>>,
+    cache: Arc<RwLock<HashMap<String, HostState>>>,
+    history: Arc<History>,
+    updates: broadcast::Sender<HostEvent>,
+}
+
+impl Poller {
+    /// Must be called from within the actix system, like [`Events::start`].
+    pub fn start(config: Arc<RwLock<Config>>, events: &Events, history: Arc<History>) -> Self {
+        let (updates, _) = broadcast::channel(256);
+        let poller = Self {
+            config,
+            cache: Arc::new(RwLock::new(HashMap::new())),
+            history,
+            updates,
+        };
+
+        let rx = events.subscribe();
+        actix_web::rt::spawn(poller.clone().run(rx));
+
+        poller
+    }
+
+    /// Host events, each sent once the cache reflects it. Hosts that changed
+    /// state between polls send a `status_changed` event.
+    pub fn subscribe(&self) -> broadcast::Receiver<HostEvent> {
+        self.updates.subscribe()
+    }
+
+    pub async fn get(&self, hostname: &str) -> HostState {
+        self.cache
+            .read()
+            .await
+            .get(hostname)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    async fn run(self, mut events: broadcast::Receiver<HostEvent>) {
+        let mut interval = actix_web::rt::time::interval(POLL_INTERVAL);
+
+        loop {
+            tokio::select! {
+                _ = interval.tick() => self.poll_all().await,
+                event = events.recv() => match event {
+                    Ok(event) => {
+                        let host = self.config.read().await.hosts.get(&event.host).cloned();
+                        if let Some(host) = host {
+                            self.poll(&event.host, &host).await;
+                        }
+                        _ = self.updates.send(event);
+                    }
+                    Err(broadcast::error::RecvError::Lagged(_)) => self.poll_all().await,
+                    Err(broadcast::error::RecvError::Closed) => break,
+                },
+            }
+        }
+    }
+
+    async fn poll_all(&self) {
+        let hosts = self.config.read().await.hosts.clone();
+        self.cache
+            .write()
+            .await
+            .retain(|name, _| hosts.contains_key(name));
+
+        let polls = hosts.iter().map(|(name, host)| async move {
+            if self.poll(name, host).await {
+                _ = self.updates.send(HostEvent {
+                    host: name.clone(),
+                    event: serde_json::json!({ "event": "status_changed" }),
+                });
+            }
+        });
+        future::join_all(polls).await;
+    }
+
+    /// Refreshes one host's cache entry and returns whether its state changed.
+    async fn poll(&self, name: &str, host: &Host) -> bool {
+        let status = fetch(host).await;
+        let now = Local::now();
+
+        let mut cache = self.cache.write().await;
+        let entry = cache.entry(name.to_string()).or_default();
+        let before = entry.state();
+
+        match status {
+            Some(status) => {
+                entry.status = Some(status);
+                entry.last_contact = Some(now);
+                entry.failures = 0;
+                entry.offline_since = None;
+            }
+            None => {
+                entry.failures += 1;
+                entry.offline_since.get_or_insert(now);
+            }
+        }
+
+        let after = entry.state();
+        if let Err(e) = self.history.record(name, after) {
+            log::warn!("Failed to record history for {name}: {e}");
+        }
+
+        after != before
+    }
+}
+
+async fn fetch(host: &Host) -> Option<Status> {
+    probe(host, POLL_TIMEOUT).await.ok()
+}
+
+/// Asks a node for its status, explaining what went wrong if it can't.
+pub async fn probe(host: &Host, timeout: Duration) -> Result<Status, String> {
+    let ip = &host.ip;
+    let request = tls::client(host)?
+        .get(format!("{ip}/status"))
+        .timeout(timeout);
+    let mut response = sign::sign(request, &host.api_key, b"")
+        .send()
+        .await
+        .map_err(|e| format!("Couldn't reach {ip}: {e}"))?;
+
+    if !response.status().is_success() {
+        return Err(format!("{ip} answered {}", response.status()));
+    }
+
+    response
+        .json::<Status>()
+        .await
+        .map_err(|e| format!("{ip} didn't answer with a status: {e}"))
+}
@@ -0,0 +1,190 @@
+use crate::config::{Host, HostTls};
+use awc::{Client, Connector};
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::crypto::{CryptoProvider, ring};
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName, UnixTime};
+use rustls::{ClientConfig, DigitallySignedStruct, RootCertStore, SignatureScheme};
+use sha2::{Digest, Sha256};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, LazyLock, Mutex};
+
+/// Client configs by TLS settings, so certificates are read once instead of
+/// on every request. Cleared whenever the config reloads.
+static CONFIGS: LazyLock<Mutex<HashMap<HostTls, Result<Arc<ClientConfig>, String>>>> =
+    LazyLock::new(Mutex::default);
+
+/// Bumped by [`clear_cache`], so every thread drops its clients too.
+static GENERATION: AtomicU64 = AtomicU64::new(0);
+
+thread_local! {
+    /// Clients by address and TLS settings, so connections are kept and
+    /// reused between polls. Clients can't leave their thread, hence one
+    /// cache per thread.
+    static CLIENTS: RefCell<(u64, HashMap<(String, Option<HostTls>), Client>)> =
+        RefCell::default();
+}
+
+/// The client for talking to `host` with its TLS settings. It has no
+/// timeout, so streams can wait forever; other requests set their own.
+pub fn client(host: &Host) -> Result<Client, String> {
+    CLIENTS.with_borrow_mut(|(generation, clients)| {
+        let current = GENERATION.load(Ordering::Relaxed);
+        if *generation != current {
+            clients.clear();
+            *generation = current;
+        }
+
+        let key = (host.ip.clone(), host.tls.clone());
+        if let Some(client) = clients.get(&key) {
+            return Ok(client.clone());
+        }
+        let client = new_client(host.tls.as_ref())?;
+        clients.insert(key, client.clone());
+        Ok(client)
+    })
+}
+
+fn new_client(tls: Option<&HostTls>) -> Result<Client, String> {
+    let builder = Client::builder().disable_timeout();
+    let Some(tls) = tls else {
+        return Ok(builder.finish());
+    };
+
+    let config = CONFIGS
+        .lock()
+        .unwrap()
+        .entry(tls.clone())
+        .or_insert_with(|| {
+            client_config(tls)
+                .map(Arc::new)
+                .map_err(|e| format!("Couldn't set up TLS: {e}"))
+        })
+        .clone()?;
+    Ok(builder
+        .connector(Connector::new().rustls_0_23(config))
+        .finish())
+}
+
+/// Forgets every client and client config, so the next request reads the
+/// certificates again and opens new connections.
+pub fn clear_cache() {
+    CONFIGS.lock().unwrap().clear();
+    GENERATION.fetch_add(1, Ordering::Relaxed);
+}
+
+/// The hex SHA-256 of a certificate, as `pin` is given.
+pub fn normalize_fingerprint(fingerprint: &str) -> String {
+    fingerprint.replace(':', "").to_ascii_lowercase()
+}
+
+fn client_config(tls: &HostTls) -> Result<ClientConfig, String> {
+    let provider = Arc::new(ring::default_provider());
+    let builder = ClientConfig::builder_with_provider(provider.clone())
+        .with_safe_default_protocol_versions()
+        .map_err(|e| e.to_string())?;
+
+    let builder = match &tls.pin {
+        Some(pin) => builder
+            .dangerous()
+            .with_custom_certificate_verifier(Arc::new(Pinned {
+                fingerprint: normalize_fingerprint(pin),
+                provider,
+            })),
+        None => {
+            let mut roots = RootCertStore::empty();
+            match &tls.ca {
+                Some(ca) => {
+                    for cert in read_certificates(ca)? {
+                        roots
+                            .add(cert)
+                            .map_err(|e| format!("{}: {e}", ca.display()))?;
+                    }
+                }
+                None => roots.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned()),
+            }
+            builder.with_root_certificates(roots)
+        }
+    };
+
+    match (&tls.client_cert, &tls.client_key) {
+        (Some(cert), Some(key)) => {
+            let key =
+                PrivateKeyDer::from_pem_file(key).map_err(|e| format!("{}: {e}", key.display()))?;
+            builder
+                .with_client_auth_cert(read_certificates(cert)?, key)
+                .map_err(|e| e.to_string())
+        }
+        _ => Ok(builder.with_no_client_auth()),
+    }
+}
+
+fn read_certificates(path: &Path) -> Result<Vec<CertificateDer<'static>>, String> {
+    CertificateDer::pem_file_iter(path)
+        .and_then(Iterator::collect)
+        .map_err(|e| format!("{}: {e}", path.display()))
+}
+
+/// Accepts exactly one certificate, whoever signed it, for nodes with
+/// self-signed certificates.
+#[derive(Debug)]
+struct Pinned {
+    fingerprint: String,
+    provider: Arc<CryptoProvider>,
+}
+
+impl ServerCertVerifier for Pinned {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        if hex::encode(Sha256::digest(end_entity)) == self.fingerprint {
+            Ok(ServerCertVerified::assertion())
+        } else {
+            Err(rustls::Error::InvalidCertificate(
+                rustls::CertificateError::ApplicationVerificationFailure,
+            ))
+        }
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls12_signature(
+            message,
+            cert,
+            dss,
+            &self.provider.signature_verification_algorithms,
+        )
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls13_signature(
+            message,
+            cert,
+            dss,
+            &self.provider.signature_verification_algorithms,
+        )
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.provider
+            .signature_verification_algorithms
+            .supported_schemes()
+    }
+}
@@ -20,6 +20,11 @@ in
       type = types.nullOr types.path;
       default = null;
     };
+    keysFile = lib.mkOption {
+      type = types.nullOr types.path;
+      default = null;
+      description = "File of named API keys with scopes and expiry, used instead of authKey";
+    };
     settings = lib.mkOption {
       type = types.attrs;
       default = { };
@@ -37,7 +42,7 @@ in
         # Use the package's service file directly
         "systemd/user/moxapi.service".source = "${cfg.package}/share/systemd/user/moxapi.service";
       }
-      // lib.optionalAttrs (cfg.authKey != null || cfg.authKeyFile != null) {
+      // lib.optionalAttrs (cfg.authKey != null || cfg.authKeyFile != null || cfg.keysFile != null) {
         # Override with drop-in file for environment variables (only if needed)
         "systemd/user/moxapi.service.d/override.conf".text = ''
           [Service]
@@ -45,6 +50,9 @@ in
           ${lib.optionalString (
             cfg.authKeyFile != null
           ) "Environment=AUTH_KEY_FILE=${toString cfg.authKeyFile}"}
+          ${lib.optionalString (
+            cfg.keysFile != null
+          ) "Environment=AUTH_KEYS_FILE=${toString cfg.keysFile}"}
         '';
       };
   };
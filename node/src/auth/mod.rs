@@ -0,0 +1,210 @@
+pub mod signed;
+
+use actix_web::http::Method;
+use anyhow::{Context, bail};
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashSet, path::Path};
+use subtle::ConstantTimeEq;
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Scope {
+    #[serde(rename = "status:read")]
+    StatusRead,
+    #[serde(rename = "notify:send")]
+    NotifySend,
+    #[serde(rename = "idle:control")]
+    IdleControl,
+    #[serde(rename = "power:control")]
+    PowerControl,
+    #[serde(rename = "schedule:control")]
+    ScheduleControl,
+}
+
+impl Scope {
+    pub const ALL: [Self; 5] = [
+        Self::StatusRead,
+        Self::NotifySend,
+        Self::IdleControl,
+        Self::PowerControl,
+        Self::ScheduleControl,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::StatusRead => "status:read",
+            Self::NotifySend => "notify:send",
+            Self::IdleControl => "idle:control",
+            Self::PowerControl => "power:control",
+            Self::ScheduleControl => "schedule:control",
+        }
+    }
+
+    /// The scope a request needs, or `None` if no route could serve it.
+    /// Reading idle, power and schedule state only needs `status:read`, while
+    /// everything under `/notify` needs `notify:send`.
+    ///
+    /// `path` has to be the percent-decoded path routes are matched against,
+    /// or `/%69dle/unlock` would get past as an unknown path.
+    pub fn required(method: &Method, path: &str) -> Option<Self> {
+        let read = matches!(*method, Method::GET | Method::HEAD);
+        match path.trim_start_matches('/').split('/').next()? {
+            "notify" => Some(Self::NotifySend),
+            "idle" if read => Some(Self::StatusRead),
+            "idle" => Some(Self::IdleControl),
+            "power" if read => Some(Self::StatusRead),
+            "power" => Some(Self::PowerControl),
+            "schedule" if read => Some(Self::StatusRead),
+            "schedule" => Some(Self::ScheduleControl),
+            "status" | "sessions" | "events" => Some(Self::StatusRead),
+            _ => None,
+        }
+    }
+}
+
+/// A named API key, client certificate, or both. The name is recorded as the
+/// caller of anything sent with it.
+#[derive(Deserialize, Clone, Debug)]
+pub struct Key {
+    pub name: String,
+    key: Option<String>,
+    /// Hex SHA-256 of a client certificate that authenticates as this key.
+    certificate: Option<String>,
+    pub scopes: Vec<Scope>,
+    pub expires: Option<DateTime<Local>>,
+}
+
+impl Key {
+    /// A key for a caller known some other way, such as a local user.
+    pub fn local(name: String, scopes: Vec<Scope>) -> Self {
+        Self {
+            name,
+            key: None,
+            certificate: None,
+            scopes,
+            expires: None,
+        }
+    }
+
+    fn secret(&self) -> Option<&str> {
+        self.key.as_deref().map(str::trim)
+    }
+
+    pub fn allows(&self, scope: Scope) -> bool {
+        self.scopes.contains(&scope)
+    }
+
+    pub fn expired(&self) -> bool {
+        self.expires.is_some_and(|expires| expires <= Local::now())
+    }
+}
+
+#[derive(Deserialize, Clone, Debug, Default)]
+pub struct Keyset {
+    keys: Vec<Key>,
+}
+
+impl Keyset {
+    /// Reads a keyset file in any format the `config` crate knows, picked by
+    /// its extension.
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let keyset: Self = config::Config::builder()
+            .add_source(config::File::from(path))
+            .build()
+            .and_then(config::Config::try_deserialize)
+            .with_context(|| format!("Failed to load keys from {}", path.display()))?;
+
+        let mut names = HashSet::new();
+        for key in &keyset.keys {
+            if key.name.is_empty() {
+                bail!("Every key in {} needs a name", path.display());
+            }
+            if !names.insert(&key.name) {
+                bail!("Key {} is listed twice in {}", key.name, path.display());
+            }
+            if key.secret().is_none_or(str::is_empty) && key.certificate.is_none() {
+                bail!(
+                    "Key {} in {} needs a key or a certificate",
+                    key.name,
+                    path.display()
+                );
+            }
+            if key.expired() {
+                log::warn!("Key {} has expired", key.name);
+            }
+        }
+
+        Ok(keyset)
+    }
+
+    /// A single key with every scope, for `AUTH_KEY` and `AUTH_KEY_FILE`.
+    pub fn single(key: &str) -> Self {
+        Self {
+            keys: vec![Key {
+                name: "default".to_string(),
+                key: Some(key.to_string()),
+                certificate: None,
+                scopes: Scope::ALL.to_vec(),
+                expires: None,
+            }],
+        }
+    }
+
+    /// The unexpired key an `Authorization` header holds, either bare or as
+    /// a `Bearer` token.
+    pub fn authenticate(&self, header: &str) -> Option<&Key> {
+        let token = match header.split_once(' ') {
+            Some((scheme, token)) if scheme.eq_ignore_ascii_case("bearer") => token,
+            _ => header,
+        }
+        .trim();
+
+        // Compare against every key so the time taken doesn't depend on
+        // which one matched.
+        let mut found = None;
+        for key in &self.keys {
+            if let Some(secret) = key.secret()
+                && !secret.is_empty()
+                && bool::from(secret.as_bytes().ct_eq(token.as_bytes()))
+            {
+                found = Some(key);
+            }
+        }
+
+        found.filter(|key| !key.expired())
+    }
+
+    /// The unexpired key `signature` was made with.
+    pub fn verify(
+        &self,
+        signature: &signed::Signature,
+        method: &str,
+        path: &str,
+        body: &[u8],
+    ) -> Option<&Key> {
+        let mut found = None;
+        for key in &self.keys {
+            if let Some(secret) = key.secret()
+                && !secret.is_empty()
+                && signature.verify(secret, method, path, body)
+            {
+                found = Some(key);
+            }
+        }
+
+        found.filter(|key| !key.expired())
+    }
+
+    /// The unexpired key a verified client certificate stands for.
+    pub fn for_certificate(&self, certificate: &crate::tls::ClientCertificate) -> Option<&Key> {
+        let fingerprint = certificate.fingerprint();
+        self.keys
+            .iter()
+            .find(|key| {
+                key.certificate.as_ref().is_some_and(|expected| {
+                    expected.replace(':', "").eq_ignore_ascii_case(&fingerprint)
+                })
+            })
+            .filter(|key| !key.expired())
+    }
+}
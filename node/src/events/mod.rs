@@ -0,0 +1,93 @@
+use crate::notify::{CloseReason, NotificationEvent};
+use serde::Serialize;
+use tokio::sync::broadcast;
+
+#[derive(Serialize, Clone, Debug)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event {
+    ScreenSaverActive {
+        active: bool,
+    },
+    Lock {
+        session: String,
+    },
+    Unlock {
+        session: String,
+    },
+    LockedHint {
+        session: String,
+        locked: bool,
+    },
+    PrepareForSleep {
+        start: bool,
+    },
+    /// Either this node's holds or logind's set of inhibitor locks changed.
+    InhibitorsChanged,
+    NotificationAction {
+        id: u32,
+        action_key: Box<str>,
+    },
+    NotificationClosed {
+        id: u32,
+        reason: CloseReason,
+    },
+}
+
+impl Event {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::ScreenSaverActive { .. } => "screen_saver_active",
+            Self::Lock { .. } => "lock",
+            Self::Unlock { .. } => "unlock",
+            Self::LockedHint { .. } => "locked_hint",
+            Self::PrepareForSleep { .. } => "prepare_for_sleep",
+            Self::InhibitorsChanged => "inhibitors_changed",
+            Self::NotificationAction { .. } => "notification_action",
+            Self::NotificationClosed { .. } => "notification_closed",
+        }
+    }
+}
+
+/// Fan-out of node state changes to every `/events` subscriber.
+#[derive(Clone)]
+pub struct EventBus {
+    tx: broadcast::Sender<Event>,
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        let (tx, _) = broadcast::channel(64);
+        Self { tx }
+    }
+
+    pub fn send(&self, event: Event) {
+        // Nobody listening is fine.
+        _ = self.tx.send(event);
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<Event> {
+        self.tx.subscribe()
+    }
+
+    /// Forwards the notification events clients care about without
+    /// following individual ids. `rx` only carries events for notifications
+    /// this node sent, so other applications' notifications never show up.
+    pub fn relay_notifications(&self, mut rx: broadcast::Receiver<NotificationEvent>) {
+        let bus = self.clone();
+        tokio::spawn(async move {
+            loop {
+                match rx.recv().await {
+                    Ok(NotificationEvent::ActionInvoked { id, action_key }) => {
+                        bus.send(Event::NotificationAction { id, action_key })
+                    }
+                    Ok(NotificationEvent::Closed { id, reason }) => {
+                        bus.send(Event::NotificationClosed { id, reason })
+                    }
+                    Ok(NotificationEvent::ActivationToken { .. })
+                    | Err(broadcast::error::RecvError::Lagged(_)) => {}
+                    Err(broadcast::error::RecvError::Closed) => break,
+                }
+            }
+        });
+    }
+}
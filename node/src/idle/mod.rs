@@ -1,4 +1,15 @@
-use zbus::Connection;
+use crate::events::{Event, EventBus};
+use futures_util::{StreamExt, stream::BoxStream};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use zbus::{
+    Connection,
+    zvariant::{OwnedFd, OwnedObjectPath},
+};
 
 #[zbus::proxy(
     name = "org.freedesktop.ScreenSaver",
@@ -15,6 +26,9 @@ trait ScreenSaver {
     async fn get_active(&self) -> zbus::Result<bool>;
 
     async fn get_active_time(&self) -> zbus::Result<u32>;
+
+    #[zbus(signal)]
+    fn active_changed(&self, new_value: bool) -> zbus::Result<()>;
 }
 
 #[zbus::proxy(
@@ -22,22 +36,298 @@ trait ScreenSaver {
     default_service = "org.freedesktop.login1",
     default_path = "/org/freedesktop/login1"
 )]
-trait LoginManager {
+pub trait LoginManager {
     async fn lock_session(&self, session_id: &str) -> zbus::Result<()>;
 
     async fn unlock_session(&self, session_id: &str) -> zbus::Result<()>;
+
+    async fn inhibit(&self, what: &str, who: &str, why: &str, mode: &str) -> zbus::Result<OwnedFd>;
+
+    async fn get_session(&self, session_id: &str) -> zbus::Result<OwnedObjectPath>;
+
+    #[zbus(name = "GetSessionByPID")]
+    async fn get_session_by_pid(&self, pid: u32) -> zbus::Result<OwnedObjectPath>;
+
+    #[zbus(name = "GetUserByPID")]
+    async fn get_user_by_pid(&self, pid: u32) -> zbus::Result<OwnedObjectPath>;
+
+    async fn list_sessions(
+        &self,
+    ) -> zbus::Result<Vec<(String, u32, String, String, OwnedObjectPath)>>;
+
+    async fn list_inhibitors(
+        &self,
+    ) -> zbus::Result<Vec<(String, String, String, String, u32, u32)>>;
+
+    async fn suspend(&self, interactive: bool) -> zbus::Result<()>;
+
+    async fn hibernate(&self, interactive: bool) -> zbus::Result<()>;
+
+    async fn hybrid_sleep(&self, interactive: bool) -> zbus::Result<()>;
+
+    async fn suspend_then_hibernate(&self, interactive: bool) -> zbus::Result<()>;
+
+    async fn reboot(&self, interactive: bool) -> zbus::Result<()>;
+
+    async fn power_off(&self, interactive: bool) -> zbus::Result<()>;
+
+    async fn can_suspend(&self) -> zbus::Result<String>;
+
+    async fn can_hibernate(&self) -> zbus::Result<String>;
+
+    async fn can_hybrid_sleep(&self) -> zbus::Result<String>;
+
+    async fn can_suspend_then_hibernate(&self) -> zbus::Result<String>;
+
+    async fn can_reboot(&self) -> zbus::Result<String>;
+
+    async fn can_power_off(&self) -> zbus::Result<String>;
+
+    #[zbus(property)]
+    fn block_inhibited(&self) -> zbus::Result<String>;
+
+    #[zbus(property)]
+    fn delay_inhibited(&self) -> zbus::Result<String>;
+
+    #[zbus(signal)]
+    fn prepare_for_sleep(&self, start: bool) -> zbus::Result<()>;
+}
+
+// The Lock and Unlock signal types would clash with our own `Lock`.
+mod session {
+    use zbus::zvariant::OwnedObjectPath;
+
+    #[zbus::proxy(
+        interface = "org.freedesktop.login1.Session",
+        default_service = "org.freedesktop.login1"
+    )]
+    pub trait Session {
+        #[zbus(property)]
+        fn id(&self) -> zbus::Result<String>;
+
+        #[zbus(property)]
+        fn locked_hint(&self) -> zbus::Result<bool>;
+
+        #[zbus(property)]
+        fn idle_hint(&self) -> zbus::Result<bool>;
+
+        #[zbus(property)]
+        fn idle_since_hint(&self) -> zbus::Result<u64>;
+
+        #[zbus(property, name = "Type")]
+        fn session_type(&self) -> zbus::Result<String>;
+
+        #[zbus(property)]
+        fn class(&self) -> zbus::Result<String>;
+
+        #[zbus(property)]
+        fn seat(&self) -> zbus::Result<(String, OwnedObjectPath)>;
+
+        #[zbus(property)]
+        fn active(&self) -> zbus::Result<bool>;
+
+        #[zbus(signal)]
+        fn lock(&self) -> zbus::Result<()>;
+
+        #[zbus(signal)]
+        fn unlock(&self) -> zbus::Result<()>;
+    }
+}
+
+use session::SessionProxy;
+
+#[zbus::proxy(
+    interface = "org.freedesktop.login1.User",
+    default_service = "org.freedesktop.login1"
+)]
+trait User {
+    #[zbus(property)]
+    fn display(&self) -> zbus::Result<(String, OwnedObjectPath)>;
+}
+
+/// Which session lock, unlock and status act on when the caller doesn't
+/// name one.
+#[derive(Default)]
+pub struct SessionTarget {
+    /// User name or uid.
+    pub user: Option<String>,
+    pub seat: Option<String>,
+}
+
+impl SessionTarget {
+    fn is_set(&self) -> bool {
+        self.user.is_some() || self.seat.is_some()
+    }
+
+    fn matches(&self, uid: u32, user: &str, seat: &str) -> bool {
+        self.user
+            .as_deref()
+            .is_none_or(|u| u == user || u == uid.to_string())
+            && self.seat.as_deref().is_none_or(|s| s == seat)
+    }
+}
+
+#[derive(Serialize)]
+pub struct SessionInfo {
+    pub id: String,
+    pub uid: u32,
+    pub user: String,
+    pub seat: String,
+    #[serde(rename = "type")]
+    pub session_type: String,
+    pub class: String,
+    pub active: bool,
+    pub locked: bool,
+    /// This is the session used when a request doesn't name one.
+    pub selected: bool,
+}
+
+fn is_graphical(session_type: &str) -> bool {
+    matches!(session_type, "x11" | "wayland" | "mir")
+}
+
+#[derive(Serialize)]
+pub struct SessionStatus {
+    pub id: String,
+    pub locked: bool,
+    pub idle: bool,
+    /// Unix time in seconds the session went idle, 0 while it isn't.
+    pub idle_since: u64,
+    #[serde(rename = "type")]
+    pub session_type: String,
+    pub class: String,
+    pub seat: String,
+    pub active: bool,
+}
+
+/// A logind inhibitor lock, held by this node or anyone else.
+#[derive(Serialize)]
+pub struct Inhibitor {
+    pub what: String,
+    pub who: String,
+    pub why: String,
+    pub mode: String,
+    pub uid: u32,
+    pub pid: u32,
+}
+
+/// What a logind inhibitor lock blocks, see `systemd-inhibit(1)`.
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum InhibitWhat {
+    Shutdown,
+    Sleep,
+    Idle,
+    HandlePowerKey,
+    HandleSuspendKey,
+    HandleHibernateKey,
+    HandleLidSwitch,
+    HandleRebootKey,
+}
+
+impl InhibitWhat {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Shutdown => "shutdown",
+            Self::Sleep => "sleep",
+            Self::Idle => "idle",
+            Self::HandlePowerKey => "handle-power-key",
+            Self::HandleSuspendKey => "handle-suspend-key",
+            Self::HandleHibernateKey => "handle-hibernate-key",
+            Self::HandleLidSwitch => "handle-lid-switch",
+            Self::HandleRebootKey => "handle-reboot-key",
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum InhibitMode {
+    #[default]
+    Block,
+    Delay,
+}
+
+impl InhibitMode {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Block => "block",
+            Self::Delay => "delay",
+        }
+    }
+}
+
+#[derive(Serialize, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Lock {
+    ScreenSaver {
+        #[serde(skip)]
+        cookie: u32,
+    },
+    /// The lock lasts as long as logind's file descriptor stays open.
+    Logind {
+        what: String,
+        mode: InhibitMode,
+        #[serde(skip)]
+        fd: Arc<OwnedFd>,
+    },
+}
+
+impl Lock {
+    fn is_same(&self, logind: Option<(&str, InhibitMode)>) -> bool {
+        match (self, logind) {
+            (Self::ScreenSaver { .. }, None) => true,
+            (Self::Logind { what, mode, .. }, Some((other_what, other_mode))) => {
+                what == other_what && *mode == other_mode
+            }
+            _ => false,
+        }
+    }
+
+    fn inhibits_idle(&self) -> bool {
+        match self {
+            Self::ScreenSaver { .. } => true,
+            Self::Logind { what, .. } => what.split(':').any(|w| w == "idle"),
+        }
+    }
+}
+
+/// An inhibitor held by this node on behalf of API callers. Holds with the
+/// same name and lock share the underlying inhibitor and are reference
+/// counted.
+#[derive(Serialize, Clone)]
+pub struct Hold {
+    pub id: u64,
+    pub name: Option<String>,
+    /// How many references each caller holds.
+    pub owners: BTreeMap<String, u32>,
+    pub reason: String,
+    pub created_at: u64,
+    pub expires_at: Option<u64>,
+    #[serde(flatten)]
+    pub lock: Lock,
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
 }
 
 pub struct Idle {
-    cookie: Option<u32>,
-    _system_conn: Connection,
+    events: EventBus,
+    holds: HashMap<u64, Hold>,
+    next_hold: u64,
+    target: SessionTarget,
+    system_conn: Connection,
     _session_conn: Connection,
     screen_saver: ScreenSaverProxy<'static>,
     login_manager: LoginManagerProxy<'static>,
 }
 
 impl Idle {
-    pub async fn new() -> anyhow::Result<Self> {
+    pub async fn new(target: SessionTarget, events: EventBus) -> anyhow::Result<Self> {
         let system_conn = zbus::Connection::system().await?;
         let session_conn = zbus::Connection::session().await?;
 
@@ -45,50 +335,381 @@ impl Idle {
         let screen_saver = ScreenSaverProxy::new(&session_conn).await?;
 
         Ok(Self {
-            cookie: None,
+            events,
+            holds: HashMap::new(),
+            next_hold: 1,
+            target,
             login_manager,
             screen_saver,
             _session_conn: session_conn,
-            _system_conn: system_conn,
+            system_conn,
         })
     }
 
-    pub async fn lock(&self) -> anyhow::Result<()> {
-        self.login_manager.lock_session("auto").await?;
+    /// The logind manager on our system bus connection, for sharing it.
+    pub fn login_manager(&self) -> LoginManagerProxy<'static> {
+        self.login_manager.clone()
+    }
+
+    pub async fn lock(&self, session: Option<&str>) -> anyhow::Result<()> {
+        let session = self.resolve_session(session).await?;
+        self.login_manager.lock_session(&session).await?;
 
         Ok(())
     }
 
-    pub async fn unlock(&self) -> anyhow::Result<()> {
-        self.login_manager.unlock_session("auto").await?;
+    pub async fn unlock(&self, session: Option<&str>) -> anyhow::Result<()> {
+        let session = self.resolve_session(session).await?;
+        self.login_manager.unlock_session(&session).await?;
 
         Ok(())
     }
 
+    /// Picks the session id to act on: the one requested, else the graphical
+    /// session matching the configured user and seat, else the session this
+    /// process runs in, else the graphical session of the user running it.
+    pub async fn resolve_session(&self, requested: Option<&str>) -> anyhow::Result<String> {
+        if let Some(session) = requested {
+            return Ok(session.to_string());
+        }
+
+        if self.target.is_set() {
+            return self.find_graphical_session().await;
+        }
+
+        let pid = std::process::id();
+        if let Ok(path) = self.login_manager.get_session_by_pid(pid).await {
+            return Ok(self.session_proxy(path).await?.id().await?);
+        }
+
+        let user = UserProxy::builder(&self.system_conn)
+            .path(self.login_manager.get_user_by_pid(pid).await?)?
+            .build()
+            .await?;
+        match user.display().await?.0 {
+            id if id.is_empty() => Err(anyhow::anyhow!("No graphical session for this user")),
+            id => Ok(id),
+        }
+    }
+
+    async fn find_graphical_session(&self) -> anyhow::Result<String> {
+        let mut found = None;
+        for (id, uid, user, seat, path) in self.login_manager.list_sessions().await? {
+            if !self.target.matches(uid, &user, &seat) {
+                continue;
+            }
+
+            let session = self.session_proxy(path).await?;
+            if !is_graphical(&session.session_type().await?) || session.class().await? != "user" {
+                continue;
+            }
+            if session.active().await? {
+                return Ok(id);
+            }
+            found.get_or_insert(id);
+        }
+
+        found.ok_or_else(|| {
+            anyhow::anyhow!("No graphical session matches the configured user and seat")
+        })
+    }
+
+    async fn session_proxy(&self, path: OwnedObjectPath) -> anyhow::Result<SessionProxy<'static>> {
+        Ok(SessionProxy::builder(&self.system_conn)
+            .path(path)?
+            .build()
+            .await?)
+    }
+
+    pub async fn list_sessions(&self) -> anyhow::Result<Box<[SessionInfo]>> {
+        let selected = self.resolve_session(None).await.ok();
+
+        let mut sessions = Vec::new();
+        for (id, uid, user, seat, path) in self.login_manager.list_sessions().await? {
+            let session = self.session_proxy(path).await?;
+            sessions.push(SessionInfo {
+                selected: selected.as_deref() == Some(id.as_str()),
+                id,
+                uid,
+                user,
+                seat,
+                session_type: session.session_type().await?,
+                class: session.class().await?,
+                active: session.active().await?,
+                locked: session.locked_hint().await?,
+            });
+        }
+
+        Ok(sessions.into_boxed_slice())
+    }
+
     pub async fn simulate_user_activity(&self) -> anyhow::Result<()> {
         self.screen_saver.simulate_user_activity().await?;
 
         Ok(())
     }
 
-    pub async fn inhibit(&mut self) -> anyhow::Result<()> {
-        match self.cookie.is_some() {
-            true => return anyhow::Result::Err(anyhow::anyhow!("Already inhibited")),
-            false => self.cookie = self.screen_saver.inhibit("", "").await.ok(),
+    /// Takes an idle inhibitor through the ScreenSaver interface.
+    pub async fn inhibit(
+        &mut self,
+        owner: &str,
+        name: Option<&str>,
+        reason: &str,
+        ttl: Option<Duration>,
+    ) -> anyhow::Result<Hold> {
+        if let Some(hold) = self.retain(owner, name, None, ttl) {
+            return Ok(hold);
+        }
+
+        let cookie = self
+            .screen_saver
+            .inhibit(name.unwrap_or("moxapi"), reason)
+            .await?;
+
+        Ok(self.insert(owner, name, reason, ttl, Lock::ScreenSaver { cookie }))
+    }
+
+    /// Takes a logind inhibitor lock, e.g. to keep the machine from
+    /// suspending or reacting to the lid switch.
+    pub async fn inhibit_logind(
+        &mut self,
+        owner: &str,
+        what: &[InhibitWhat],
+        mode: InhibitMode,
+        name: Option<&str>,
+        reason: &str,
+        ttl: Option<Duration>,
+    ) -> anyhow::Result<Hold> {
+        let what = what
+            .iter()
+            .map(|w| w.as_str())
+            .collect::<Vec<_>>()
+            .join(":");
+        if let Some(hold) = self.retain(owner, name, Some((&what, mode)), ttl) {
+            return Ok(hold);
+        }
+
+        let fd = self
+            .login_manager
+            .inhibit(&what, name.unwrap_or("moxapi"), reason, mode.as_str())
+            .await?;
+        let lock = Lock::Logind {
+            what,
+            mode,
+            fd: Arc::new(fd),
+        };
+
+        Ok(self.insert(owner, name, reason, ttl, lock))
+    }
+
+    /// Bumps `owner`'s reference count on an existing named hold on the same
+    /// lock, keeping whichever expiry is later. Anonymous holds never match.
+    fn retain(
+        &mut self,
+        owner: &str,
+        name: Option<&str>,
+        logind: Option<(&str, InhibitMode)>,
+        ttl: Option<Duration>,
+    ) -> Option<Hold> {
+        let name = name?;
+        let expires_at = ttl.map(|ttl| unix_now().saturating_add(ttl.as_secs()));
+        let hold = self
+            .holds
+            .values_mut()
+            .find(|h| h.name.as_deref() == Some(name) && h.lock.is_same(logind))?;
+
+        *hold.owners.entry(owner.to_string()).or_default() += 1;
+        hold.expires_at = hold.expires_at.zip(expires_at).map(|(a, b)| a.max(b));
+
+        Some(hold.clone())
+    }
+
+    fn insert(
+        &mut self,
+        owner: &str,
+        name: Option<&str>,
+        reason: &str,
+        ttl: Option<Duration>,
+        lock: Lock,
+    ) -> Hold {
+        let now = unix_now();
+        let hold = Hold {
+            id: self.next_hold,
+            name: name.map(str::to_string),
+            owners: BTreeMap::from([(owner.to_string(), 1)]),
+            reason: reason.to_string(),
+            created_at: now,
+            expires_at: ttl.map(|ttl| now.saturating_add(ttl.as_secs())),
+            lock,
+        };
+        self.next_hold += 1;
+        self.holds.insert(hold.id, hold.clone());
+        self.events.send(Event::InhibitorsChanged);
+
+        hold
+    }
+
+    /// Drops one of `owner`'s references to a hold, releasing the inhibitor
+    /// once nobody holds it anymore. Returns `false` if there is no such hold
+    /// or `owner` doesn't hold it.
+    pub async fn uninhibit(&mut self, owner: &str, id: u64) -> anyhow::Result<bool> {
+        let Some(hold) = self.holds.get_mut(&id) else {
+            return Ok(false);
+        };
+        let Some(count) = hold.owners.get_mut(owner) else {
+            return Ok(false);
+        };
+
+        *count -= 1;
+        if *count == 0 {
+            hold.owners.remove(owner);
+        }
+        if hold.owners.is_empty() {
+            self.release(id).await?;
+        }
+
+        Ok(true)
+    }
+
+    /// Drops all of `owner`'s references, releasing the inhibitors nobody
+    /// else holds. Other callers' references are left alone.
+    pub async fn uninhibit_owned(&mut self, owner: &str) -> anyhow::Result<()> {
+        let mut unused = Vec::new();
+        for hold in self.holds.values_mut() {
+            if hold.owners.remove(owner).is_some() && hold.owners.is_empty() {
+                unused.push(hold.id);
+            }
+        }
+        for id in unused {
+            self.release(id).await?;
         }
 
         Ok(())
     }
 
-    pub async fn uninhibit(&mut self) -> anyhow::Result<()> {
-        match self.cookie.take() {
-            Some(cookie) => self.screen_saver.un_inhibit(cookie).await?,
-            None => return anyhow::Result::Err(anyhow::anyhow!("Not inhibited")),
+    pub fn has_expired_holds(&self) -> bool {
+        let now = unix_now();
+        self.holds
+            .values()
+            .any(|h| h.expires_at.is_some_and(|t| t <= now))
+    }
+
+    pub async fn release_expired(&mut self) -> anyhow::Result<()> {
+        let now = unix_now();
+        let expired: Vec<_> = self
+            .holds
+            .values()
+            .filter(|h| h.expires_at.is_some_and(|t| t <= now))
+            .map(|h| h.id)
+            .collect();
+        for id in expired {
+            self.release(id).await?;
+        }
+
+        Ok(())
+    }
+
+    async fn release(&mut self, id: u64) -> anyhow::Result<()> {
+        let Some(hold) = self.holds.remove(&id) else {
+            return Ok(());
+        };
+
+        self.events.send(Event::InhibitorsChanged);
+        if let Lock::ScreenSaver { cookie } = hold.lock {
+            self.screen_saver.un_inhibit(cookie).await?;
         }
 
         Ok(())
     }
 
+    /// Turns ScreenSaver and logind signals into [`Event`]s. Lock signals are
+    /// followed on the session resolved at startup, if there is one.
+    pub async fn watch(&self) -> anyhow::Result<()> {
+        let mut active_changed = self.screen_saver.receive_active_changed().await?;
+        let mut prepare_for_sleep = self.login_manager.receive_prepare_for_sleep().await?;
+        let mut block_inhibited = self.login_manager.receive_block_inhibited_changed().await;
+        let mut delay_inhibited = self.login_manager.receive_delay_inhibited_changed().await;
+        let mut session_events = match self.session_events().await {
+            Ok(events) => events,
+            Err(e) => {
+                log::warn!("Not following session lock signals: {e}");
+                futures_util::stream::pending().boxed()
+            }
+        };
+
+        let events = self.events.clone();
+        tokio::spawn(async move {
+            loop {
+                let event = tokio::select! {
+                    Some(signal) = active_changed.next() => match signal.args() {
+                        Ok(args) => Event::ScreenSaverActive { active: args.new_value },
+                        Err(e) => {
+                            log::warn!("Malformed ActiveChanged signal: {e}");
+                            continue;
+                        }
+                    },
+                    Some(signal) = prepare_for_sleep.next() => match signal.args() {
+                        Ok(args) => Event::PrepareForSleep { start: args.start },
+                        Err(e) => {
+                            log::warn!("Malformed PrepareForSleep signal: {e}");
+                            continue;
+                        }
+                    },
+                    Some(_) = block_inhibited.next() => Event::InhibitorsChanged,
+                    Some(_) = delay_inhibited.next() => Event::InhibitorsChanged,
+                    Some(event) = session_events.next() => event,
+                    else => break,
+                };
+
+                events.send(event);
+            }
+        });
+
+        Ok(())
+    }
+
+    /// Lock, Unlock and LockedHint changes of the default session.
+    async fn session_events(&self) -> anyhow::Result<BoxStream<'static, Event>> {
+        let id = self.resolve_session(None).await?;
+        let session = self
+            .session_proxy(self.login_manager.get_session(&id).await?)
+            .await?;
+
+        let lock_id = id.clone();
+        let lock = session.receive_lock().await?.map(move |_| Event::Lock {
+            session: lock_id.clone(),
+        });
+        let unlock_id = id.clone();
+        let unlock = session.receive_unlock().await?.map(move |_| Event::Unlock {
+            session: unlock_id.clone(),
+        });
+        let locked_hint = session
+            .receive_locked_hint_changed()
+            .await
+            .filter_map(move |change| {
+                let session = id.clone();
+                async move {
+                    change
+                        .get()
+                        .await
+                        .inspect_err(|e| log::warn!("Failed to read LockedHint: {e}"))
+                        .ok()
+                        .map(|locked| Event::LockedHint { session, locked })
+                }
+            });
+
+        Ok(
+            futures_util::stream::select_all([lock.boxed(), unlock.boxed(), locked_hint.boxed()])
+                .boxed(),
+        )
+    }
+
+    pub fn holds(&self) -> Box<[Hold]> {
+        let mut holds: Vec<_> = self.holds.values().cloned().collect();
+        holds.sort_by_key(|h| h.id);
+        holds.into_boxed_slice()
+    }
+
     pub async fn get_active(&self) -> anyhow::Result<bool> {
         self.screen_saver
             .get_active()
@@ -104,6 +725,44 @@ impl Idle {
     }
 
     pub fn get_inhibited(&self) -> bool {
-        self.cookie.is_some()
+        self.holds.values().any(|h| h.lock.inhibits_idle())
+    }
+
+    pub async fn get_session_status(&self, session: Option<&str>) -> anyhow::Result<SessionStatus> {
+        let id = self.resolve_session(session).await?;
+        let path = self.login_manager.get_session(&id).await?;
+        let session = self.session_proxy(path).await?;
+
+        let idle = session.idle_hint().await?;
+        Ok(SessionStatus {
+            id: session.id().await?,
+            locked: session.locked_hint().await?,
+            idle,
+            idle_since: match idle {
+                true => session.idle_since_hint().await? / 1_000_000,
+                false => 0,
+            },
+            session_type: session.session_type().await?,
+            class: session.class().await?,
+            seat: session.seat().await?.0,
+            active: session.active().await?,
+        })
+    }
+
+    pub async fn list_inhibitors(&self) -> anyhow::Result<Box<[Inhibitor]>> {
+        Ok(self
+            .login_manager
+            .list_inhibitors()
+            .await?
+            .into_iter()
+            .map(|(what, who, why, mode, uid, pid)| Inhibitor {
+                what,
+                who,
+                why,
+                mode,
+                uid,
+                pid,
+            })
+            .collect())
     }
 }
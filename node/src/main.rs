@@ -1,26 +1,41 @@
+mod auth;
+mod events;
 mod idle;
 mod notify;
+mod power;
+mod scheduler;
+mod settings;
+mod socket;
+mod tls;
 
 use actix_cors::Cors;
 use actix_web::{
-    App, Error, HttpResponse, HttpServer,
+    App, Error, HttpMessage, HttpRequest, HttpResponse, HttpServer,
     body::BoxBody,
+    delete,
     dev::{Service, ServiceRequest, ServiceResponse, Transform},
     get,
     middleware::{self, DefaultHeaders},
-    post, web,
+    patch, post, web,
 };
 use clap::Parser;
 use env_logger::Builder;
-use futures_util::future::{LocalBoxFuture, Ready, ok};
+use futures_util::{
+    StreamExt,
+    future::{LocalBoxFuture, Ready, ok},
+};
 use log::LevelFilter;
 use serde::{Deserialize, Serialize};
-use std::{env, rc::Rc, sync::Arc};
-use tokio::sync::RwLock;
+use std::{net::IpAddr, path::PathBuf, rc::Rc, sync::Arc, time::Duration};
+use tokio::sync::{RwLock, broadcast};
 
 struct State {
+    events: events::EventBus,
     idle: Arc<RwLock<idle::Idle>>,
     notify: notify::NotificationManager,
+    progress: notify::progress::ProgressManager,
+    power: power::Power,
+    scheduler: scheduler::Scheduler,
 }
 
 #[derive(Serialize)]
@@ -28,35 +43,205 @@ struct Status {
     active: bool,
     active_time: u32,
     inhibited: bool,
+    holds: Box<[idle::Hold]>,
+    session: Option<idle::SessionStatus>,
+    inhibitors: Box<[idle::Inhibitor]>,
+}
+
+#[derive(Deserialize)]
+struct SessionQuery {
+    session: Option<String>,
 }
 
 #[get("/status")]
-async fn get_status(data: web::Data<State>) -> Result<HttpResponse, actix_web::Error> {
+async fn get_status(
+    data: web::Data<State>,
+    query: web::Query<SessionQuery>,
+) -> Result<HttpResponse, actix_web::Error> {
     let idle = data.idle.read().await;
+    let (active, active_time) = match (idle.get_active().await, idle.get_active_time().await) {
+        (Ok(active), Ok(active_time)) => (active, active_time),
+        (Err(e), _) | (_, Err(e)) => {
+            return Ok(dbus_error_response(&e, "Failed to read idle status"));
+        }
+    };
     let status = Status {
-        active: idle.get_active().await.unwrap(),
-        active_time: idle.get_active_time().await.unwrap(),
+        active,
+        active_time,
         inhibited: idle.get_inhibited(),
+        holds: idle.holds(),
+        session: idle
+            .get_session_status(query.session.as_deref())
+            .await
+            .inspect_err(|e| log::warn!("Failed to read session properties: {e}"))
+            .ok(),
+        inhibitors: idle
+            .list_inhibitors()
+            .await
+            .inspect_err(|e| log::warn!("Failed to list inhibitors: {e}"))
+            .unwrap_or_default(),
     };
 
     Ok(HttpResponse::Ok().json(status))
 }
 
+#[get("/sessions")]
+async fn get_sessions(data: web::Data<State>) -> Result<HttpResponse, actix_web::Error> {
+    match data.idle.read().await.list_sessions().await {
+        Ok(sessions) => Ok(HttpResponse::Ok().json(sessions)),
+        Err(e) => Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+            "status": "error",
+            "message": format!("Failed to list sessions: {e}")
+        }))),
+    }
+}
+
+/// Parses an optional JSON body, so endpoints that predate their request
+/// bodies keep working for clients that send none.
+fn optional_json<T: serde::de::DeserializeOwned>(
+    body: &web::Bytes,
+) -> Result<Option<T>, actix_web::Error> {
+    if body.is_empty() {
+        return Ok(None);
+    }
+
+    serde_json::from_slice(body)
+        .map(Some)
+        .map_err(actix_web::error::ErrorBadRequest)
+}
+
+#[derive(Deserialize, Default)]
+struct InhibitRequest {
+    name: Option<String>,
+    #[serde(default)]
+    reason: String,
+    /// Seconds until the hold is released automatically.
+    ttl: Option<u64>,
+}
+
+#[derive(Deserialize)]
+struct LogindInhibitRequest {
+    what: Box<[idle::InhibitWhat]>,
+    #[serde(default)]
+    mode: idle::InhibitMode,
+    name: Option<String>,
+    #[serde(default)]
+    reason: String,
+    ttl: Option<u64>,
+}
+
+#[derive(Deserialize)]
+struct UninhibitRequest {
+    id: u64,
+}
+
+#[get("/idle/inhibit")]
+async fn get_idle_inhibit(data: web::Data<State>) -> Result<HttpResponse, actix_web::Error> {
+    Ok(HttpResponse::Ok().json(data.idle.read().await.holds()))
+}
+
 #[post("/idle/inhibit")]
-async fn post_idle_inhibit(data: web::Data<State>) -> Result<HttpResponse, actix_web::Error> {
-    if data.idle.write().await.inhibit().await.is_ok() {
-        Ok(HttpResponse::Ok().finish())
-    } else {
-        Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+async fn post_idle_inhibit(
+    req: HttpRequest,
+    data: web::Data<State>,
+    body: web::Bytes,
+) -> Result<HttpResponse, actix_web::Error> {
+    let req_body: InhibitRequest = optional_json(&body)?.unwrap_or_default();
+    match data
+        .idle
+        .write()
+        .await
+        .inhibit(
+            &caller(&req),
+            req_body.name.as_deref(),
+            &req_body.reason,
+            req_body.ttl.map(Duration::from_secs),
+        )
+        .await
+    {
+        Ok(hold) => Ok(HttpResponse::Ok().json(hold)),
+        Err(e) => Ok(HttpResponse::InternalServerError().json(serde_json::json!({
             "status": "error",
-            "message": "Failed to idle inhibit"
-        })))
+            "message": format!("Failed to idle inhibit: {e}")
+        }))),
+    }
+}
+
+#[post("/idle/inhibit/logind")]
+async fn post_idle_inhibit_logind(
+    req: HttpRequest,
+    data: web::Data<State>,
+    req_body: web::Json<LogindInhibitRequest>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if req_body.what.is_empty() {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "status": "error",
+            "message": "Nothing to inhibit"
+        })));
+    }
+
+    match data
+        .idle
+        .write()
+        .await
+        .inhibit_logind(
+            &caller(&req),
+            &req_body.what,
+            req_body.mode,
+            req_body.name.as_deref(),
+            &req_body.reason,
+            req_body.ttl.map(Duration::from_secs),
+        )
+        .await
+    {
+        Ok(hold) => Ok(HttpResponse::Ok().json(hold)),
+        Err(e) => Ok(dbus_error_response(&e, "Failed to take inhibitor lock")),
+    }
+}
+
+async fn release_hold(data: &State, owner: &str, id: u64) -> HttpResponse {
+    match data.idle.write().await.uninhibit(owner, id).await {
+        Ok(true) => HttpResponse::Ok().finish(),
+        Ok(false) => HttpResponse::NotFound().json(serde_json::json!({
+            "status": "error",
+            "message": "Unknown inhibitor"
+        })),
+        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({
+            "status": "error",
+            "message": format!("Failed to idle uninhibit: {e}")
+        })),
     }
 }
 
+#[delete("/idle/inhibit/{id}")]
+async fn delete_idle_inhibit(
+    req: HttpRequest,
+    data: web::Data<State>,
+    path: web::Path<u64>,
+) -> Result<HttpResponse, actix_web::Error> {
+    Ok(release_hold(&data, &caller(&req), path.into_inner()).await)
+}
+
+/// Releases one hold when given its id, or every hold the caller took
+/// otherwise.
 #[post("/idle/uninhibit")]
-async fn post_idle_uninhibit(data: web::Data<State>) -> Result<HttpResponse, actix_web::Error> {
-    if data.idle.write().await.uninhibit().await.is_ok() {
+async fn post_idle_uninhibit(
+    req: HttpRequest,
+    data: web::Data<State>,
+    body: web::Bytes,
+) -> Result<HttpResponse, actix_web::Error> {
+    if let Some(req_body) = optional_json::<UninhibitRequest>(&body)? {
+        return Ok(release_hold(&data, &caller(&req), req_body.id).await);
+    }
+
+    if data
+        .idle
+        .write()
+        .await
+        .uninhibit_owned(&caller(&req))
+        .await
+        .is_ok()
+    {
         Ok(HttpResponse::Ok().finish())
     } else {
         Ok(HttpResponse::InternalServerError().json(serde_json::json!({
@@ -67,8 +252,18 @@ async fn post_idle_uninhibit(data: web::Data<State>) -> Result<HttpResponse, act
 }
 
 #[post("/idle/lock")]
-async fn post_idle_lock(data: web::Data<State>) -> Result<HttpResponse, actix_web::Error> {
-    if data.idle.read().await.lock().await.is_ok() {
+async fn post_idle_lock(
+    data: web::Data<State>,
+    query: web::Query<SessionQuery>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if data
+        .idle
+        .read()
+        .await
+        .lock(query.session.as_deref())
+        .await
+        .is_ok()
+    {
         Ok(HttpResponse::Ok().finish())
     } else {
         Ok(HttpResponse::InternalServerError().json(serde_json::json!({
@@ -79,8 +274,18 @@ async fn post_idle_lock(data: web::Data<State>) -> Result<HttpResponse, actix_we
 }
 
 #[post("/idle/unlock")]
-async fn post_idle_unlock(data: web::Data<State>) -> Result<HttpResponse, actix_web::Error> {
-    if data.idle.read().await.unlock().await.is_ok() {
+async fn post_idle_unlock(
+    data: web::Data<State>,
+    query: web::Query<SessionQuery>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if data
+        .idle
+        .read()
+        .await
+        .unlock(query.session.as_deref())
+        .await
+        .is_ok()
+    {
         Ok(HttpResponse::Ok().finish())
     } else {
         Ok(HttpResponse::InternalServerError().json(serde_json::json!({
@@ -111,6 +316,141 @@ async fn post_simulate_user_activity(
     }
 }
 
+/// Maps a failed D-Bus call onto a response: 503 when nothing is there to
+/// answer it, 403 when we aren't allowed to make it, 400 when the service
+/// rejected our arguments.
+fn dbus_error_response(e: &anyhow::Error, message: &str) -> HttpResponse {
+    let error = e
+        .downcast_ref::<zbus::Error>()
+        .cloned()
+        .map(zbus::fdo::Error::from);
+    let mut response = match error {
+        Some(
+            zbus::fdo::Error::ServiceUnknown(_)
+            | zbus::fdo::Error::NameHasNoOwner(_)
+            | zbus::fdo::Error::NoServer(_)
+            | zbus::fdo::Error::Disconnected(_)
+            | zbus::fdo::Error::ZBus(zbus::Error::InputOutput(_)),
+        ) => HttpResponse::ServiceUnavailable(),
+        Some(
+            zbus::fdo::Error::AccessDenied(_)
+            | zbus::fdo::Error::InteractiveAuthorizationRequired(_),
+        ) => HttpResponse::Forbidden(),
+        Some(zbus::fdo::Error::InvalidArgs(_) | zbus::fdo::Error::InvalidSignature(_)) => {
+            HttpResponse::BadRequest()
+        }
+        _ => HttpResponse::InternalServerError(),
+    };
+
+    response.json(serde_json::json!({
+        "status": "error",
+        "message": format!("{message}: {e}")
+    }))
+}
+
+#[get("/power")]
+async fn get_power(data: web::Data<State>) -> Result<HttpResponse, actix_web::Error> {
+    match data.power.capabilities().await {
+        Ok(capabilities) => Ok(HttpResponse::Ok().json(capabilities)),
+        Err(e) => Ok(dbus_error_response(
+            &e,
+            "Failed to query power capabilities",
+        )),
+    }
+}
+
+#[derive(Deserialize)]
+struct ConfirmQuery {
+    #[serde(default)]
+    confirm: bool,
+}
+
+#[post("/power/{action}")]
+async fn post_power(
+    data: web::Data<State>,
+    path: web::Path<power::PowerAction>,
+    query: web::Query<ConfirmQuery>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if !query.confirm {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "status": "error",
+            "message": "Power actions require confirm=true"
+        })));
+    }
+
+    match data.power.run(path.into_inner()).await {
+        Ok(()) => Ok(HttpResponse::Ok().finish()),
+        Err(e) => Ok(dbus_error_response(&e, "Failed to run power action")),
+    }
+}
+
+#[get("/schedule")]
+async fn get_schedule(data: web::Data<State>) -> Result<HttpResponse, actix_web::Error> {
+    Ok(HttpResponse::Ok().json(data.scheduler.list().await))
+}
+
+#[post("/schedule")]
+async fn post_schedule(
+    req: HttpRequest,
+    data: web::Data<State>,
+    req_body: web::Json<scheduler::JobRequest>,
+) -> Result<HttpResponse, actix_web::Error> {
+    // Scheduling an action mustn't let a key do more than it could directly.
+    let scope = req_body.action.scope();
+    if let Some(key) = req.extensions().get::<auth::Key>()
+        && !key.allows(scope)
+    {
+        return Ok(forbidden(scope));
+    }
+
+    let (schedule, next_run) = match req_body.schedule() {
+        Ok(schedule) => schedule,
+        Err(e) => {
+            return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                "status": "error",
+                "message": format!("Invalid schedule: {e}")
+            })));
+        }
+    };
+
+    let req_body = req_body.into_inner();
+    match data
+        .scheduler
+        .add(
+            req_body.action,
+            schedule,
+            next_run,
+            req_body.warn_before,
+            caller(&req),
+        )
+        .await
+    {
+        Ok(job) => Ok(HttpResponse::Ok().json(job)),
+        Err(e) => Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+            "status": "error",
+            "message": format!("Failed to schedule job: {e}")
+        }))),
+    }
+}
+
+#[delete("/schedule/{id}")]
+async fn delete_schedule(
+    data: web::Data<State>,
+    path: web::Path<u64>,
+) -> Result<HttpResponse, actix_web::Error> {
+    match data.scheduler.cancel(path.into_inner()).await {
+        Ok(true) => Ok(HttpResponse::Ok().finish()),
+        Ok(false) => Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "status": "error",
+            "message": "Unknown job"
+        }))),
+        Err(e) => Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+            "status": "error",
+            "message": format!("Failed to cancel job: {e}")
+        }))),
+    }
+}
+
 #[get("/notify/capabilities")]
 async fn get_notify_capabilities(data: web::Data<State>) -> Result<HttpResponse, actix_web::Error> {
     match data.notify.get_capabilities().await {
@@ -119,39 +459,424 @@ async fn get_notify_capabilities(data: web::Data<State>) -> Result<HttpResponse,
     }
 }
 
+#[derive(Deserialize)]
+struct NotificationAction {
+    key: Box<str>,
+    label: Box<str>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "kebab-case")]
+struct NotificationHints {
+    action_icons: Option<bool>,
+    urgency: Option<notify::Urgency>,
+    category: Option<Box<str>>,
+    desktop_entry: Option<Box<str>>,
+    image_data: Option<notify::ImageData>,
+    image_path: Option<Box<str>>,
+    resident: Option<bool>,
+    sound_file: Option<Box<str>>,
+    sound_name: Option<Box<str>>,
+    suppress_sound: Option<bool>,
+    transient: Option<bool>,
+    x: Option<i32>,
+    y: Option<i32>,
+}
+
 #[derive(Deserialize)]
 struct NotificationRequest {
     summary: Box<str>,
     body: Box<str>,
     timeout: i32,
+    #[serde(default)]
     id: u32,
+    app_name: Option<Box<str>>,
+    app_icon: Option<Box<str>>,
+    #[serde(default)]
+    actions: Box<[NotificationAction]>,
+    #[serde(default)]
+    hints: NotificationHints,
+}
+
+impl NotificationRequest {
+    fn apply<'a>(
+        &'a self,
+        mut builder: notify::NotificationBuilder<'a>,
+    ) -> notify::NotificationBuilder<'a> {
+        builder = builder
+            .with_summary(&self.summary)
+            .with_body(&self.body)
+            .with_timeout(self.timeout)
+            .with_id(self.id);
+
+        if let Some(app_name) = &self.app_name {
+            builder = builder.with_app_name(app_name);
+        }
+        if let Some(app_icon) = &self.app_icon {
+            builder = builder.with_app_icon(app_icon);
+        }
+        for action in &self.actions {
+            builder = builder.with_action(&action.key, &action.label);
+        }
+
+        let hints = &self.hints;
+        if let Some(action_icons) = hints.action_icons {
+            builder = builder.with_action_icons(action_icons);
+        }
+        if let Some(urgency) = hints.urgency {
+            builder = builder.with_urgency(urgency);
+        }
+        if let Some(category) = &hints.category {
+            builder = builder.with_category(category);
+        }
+        if let Some(desktop_entry) = &hints.desktop_entry {
+            builder = builder.with_desktop_entry(desktop_entry);
+        }
+        if let Some(image_data) = &hints.image_data {
+            builder = builder.with_image_data(image_data.clone());
+        }
+        if let Some(image_path) = &hints.image_path {
+            builder = builder.with_image_path(image_path);
+        }
+        if let Some(resident) = hints.resident {
+            builder = builder.with_resident(resident);
+        }
+        if let Some(sound_file) = &hints.sound_file {
+            builder = builder.with_sound_file(sound_file);
+        }
+        if let Some(sound_name) = &hints.sound_name {
+            builder = builder.with_sound_name(sound_name);
+        }
+        if let Some(suppress_sound) = hints.suppress_sound {
+            builder = builder.with_suppress_sound(suppress_sound);
+        }
+        if let Some(transient) = hints.transient {
+            builder = builder.with_transient(transient);
+        }
+        if let (Some(x), Some(y)) = (hints.x, hints.y) {
+            builder = builder.with_position(x, y);
+        }
+
+        builder
+    }
+}
+
+/// The name of the key the request used, or its address if it has none.
+fn caller(req: &HttpRequest) -> String {
+    if let Some(key) = req.extensions().get::<auth::Key>() {
+        return key.name.clone();
+    }
+
+    req.peer_addr()
+        .map(|addr| addr.ip().to_string())
+        .unwrap_or_default()
 }
 
 #[post("/notify")]
 async fn post_notify(
+    req: HttpRequest,
     data: web::Data<State>,
     req_body: web::Json<NotificationRequest>,
 ) -> Result<HttpResponse, actix_web::Error> {
-    let builder = data.notify.builder().await;
-    builder
-        .with_summary(&req_body.summary)
-        .with_body(&req_body.body)
-        .with_timeout(req_body.timeout)
-        .with_id(req_body.id)
-        .send()
+    let caller = caller(&req);
+    let builder = data.notify.builder().await.with_caller(&caller);
+    match req_body.apply(builder).send().await {
+        Ok(id) => Ok(HttpResponse::Ok().json(serde_json::json!({ "id": id }))),
+        Err(e) => Ok(dbus_error_response(&e, "Failed to send notification")),
+    }
+}
+
+#[get("/notify")]
+async fn get_notify(data: web::Data<State>) -> Result<HttpResponse, actix_web::Error> {
+    Ok(HttpResponse::Ok().json(data.notify.sent()))
+}
+
+#[delete("/notify/{id}")]
+async fn delete_notify(
+    data: web::Data<State>,
+    path: web::Path<u32>,
+) -> Result<HttpResponse, actix_web::Error> {
+    match data.notify.close(path.into_inner()).await {
+        Ok(true) => Ok(HttpResponse::Ok().finish()),
+        Ok(false) => Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "status": "error",
+            "message": "Unknown notification"
+        }))),
+        Err(e) => Ok(dbus_error_response(&e, "Failed to close notification")),
+    }
+}
+
+#[derive(Deserialize)]
+struct ProgressRequest {
+    summary: Box<str>,
+    #[serde(default)]
+    body: Box<str>,
+    #[serde(default)]
+    value: u8,
+}
+
+#[derive(Deserialize)]
+struct ProgressUpdate {
+    value: Option<u8>,
+    body: Option<Box<str>>,
+}
+
+#[derive(Deserialize)]
+struct ProgressFinish {
+    state: notify::progress::FinalState,
+    body: Option<Box<str>>,
+}
+
+fn unknown_progress() -> HttpResponse {
+    HttpResponse::NotFound().json(serde_json::json!({
+        "status": "error",
+        "message": "Unknown progress notification"
+    }))
+}
+
+#[get("/notify/progress")]
+async fn get_notify_progress(data: web::Data<State>) -> Result<HttpResponse, actix_web::Error> {
+    Ok(HttpResponse::Ok().json(data.progress.list().await))
+}
+
+#[post("/notify/progress")]
+async fn post_notify_progress(
+    req: HttpRequest,
+    data: web::Data<State>,
+    req_body: web::Json<ProgressRequest>,
+) -> Result<HttpResponse, actix_web::Error> {
+    match data
+        .progress
+        .create(
+            &req_body.summary,
+            &req_body.body,
+            req_body.value,
+            &caller(&req),
+        )
+        .await
+    {
+        Ok(progress) => Ok(HttpResponse::Ok().json(progress)),
+        Err(e) => Ok(dbus_error_response(
+            &e,
+            "Failed to send progress notification",
+        )),
+    }
+}
+
+#[patch("/notify/progress/{id}")]
+async fn patch_notify_progress(
+    data: web::Data<State>,
+    path: web::Path<u32>,
+    req_body: web::Json<ProgressUpdate>,
+) -> Result<HttpResponse, actix_web::Error> {
+    match data
+        .progress
+        .update(path.into_inner(), req_body.value, req_body.body.as_deref())
         .await
-        .unwrap();
+    {
+        Ok(Some(progress)) => Ok(HttpResponse::Ok().json(progress)),
+        Ok(None) => Ok(unknown_progress()),
+        Err(e) => Ok(dbus_error_response(
+            &e,
+            "Failed to update progress notification",
+        )),
+    }
+}
+
+#[post("/notify/progress/{id}/finish")]
+async fn post_notify_progress_finish(
+    data: web::Data<State>,
+    path: web::Path<u32>,
+    req_body: web::Json<ProgressFinish>,
+) -> Result<HttpResponse, actix_web::Error> {
+    match data
+        .progress
+        .finish(path.into_inner(), req_body.state, req_body.body.as_deref())
+        .await
+    {
+        Ok(Some(progress)) => Ok(HttpResponse::Ok().json(progress)),
+        Ok(None) => Ok(unknown_progress()),
+        Err(e) => Ok(dbus_error_response(
+            &e,
+            "Failed to finish progress notification",
+        )),
+    }
+}
+
+#[derive(Deserialize)]
+struct WaitQuery {
+    timeout: Option<u64>,
+}
 
-    Ok(HttpResponse::Ok().finish())
+#[get("/notify/{id}/wait")]
+async fn get_notify_wait(
+    data: web::Data<State>,
+    path: web::Path<u32>,
+    query: web::Query<WaitQuery>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let timeout = Duration::from_secs(query.timeout.unwrap_or(30).min(300));
+    match data.notify.wait(path.into_inner(), timeout).await {
+        Some(events) => Ok(HttpResponse::Ok().json(events)),
+        None => Ok(HttpResponse::NoContent().finish()),
+    }
+}
+
+#[derive(Deserialize)]
+struct EventsQuery {
+    id: Option<u32>,
+}
+
+#[get("/notify/events")]
+async fn get_notify_events(
+    data: web::Data<State>,
+    query: web::Query<EventsQuery>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let id = query.id;
+    Ok(sse_response(
+        data.notify.subscribe(),
+        move |event| id.is_none_or(|id| id == event.id()),
+        notify::NotificationEvent::name,
+    ))
+}
+
+#[get("/events")]
+async fn get_events(data: web::Data<State>) -> Result<HttpResponse, actix_web::Error> {
+    Ok(sse_response(
+        data.events.subscribe(),
+        |_| true,
+        events::Event::name,
+    ))
+}
+
+/// How often an idle event stream sends a comment, so proxies keep the
+/// connection open and clients notice when it drops.
+const SSE_KEEP_ALIVE: Duration = Duration::from_secs(15);
+
+/// Streams the events `keep` lets through as Server-Sent Events named by
+/// `name`, with their JSON as data.
+fn sse_response<T>(
+    rx: broadcast::Receiver<T>,
+    keep: impl Fn(&T) -> bool + 'static,
+    name: fn(&T) -> &'static str,
+) -> HttpResponse
+where
+    T: Serialize + Clone + 'static,
+{
+    let stream = futures::stream::unfold((rx, keep), move |(mut rx, keep)| async move {
+        loop {
+            let event = tokio::select! {
+                event = rx.recv() => event,
+                _ = tokio::time::sleep(SSE_KEEP_ALIVE) => {
+                    let payload = web::Bytes::from_static(b": keep-alive\n\n");
+                    return Some((Ok::<_, actix_web::Error>(payload), (rx, keep)));
+                }
+            };
+
+            match event {
+                Ok(event) if keep(&event) => {
+                    let payload = format!(
+                        "event: {}\ndata: {}\n\n",
+                        name(&event),
+                        serde_json::to_string(&event).ok()?
+                    );
+                    return Some((Ok(web::Bytes::from(payload)), (rx, keep)));
+                }
+                Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(broadcast::error::RecvError::Closed) => return None,
+            }
+        }
+    });
+
+    HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header(("Cache-Control", "no-cache"))
+        .streaming(stream)
+}
+
+fn forbidden(scope: auth::Scope) -> HttpResponse {
+    HttpResponse::Forbidden().json(serde_json::json!({
+        "status": "error",
+        "message": format!("This key lacks the {} scope", scope.as_str())
+    }))
+}
+
+fn unauthorized(reason: &str) -> HttpResponse {
+    HttpResponse::Unauthorized().body(format!("Unauthorized: {reason}"))
+}
+
+/// Checks a signed request, reading its body to do so and putting it back
+/// for the handler.
+async fn verify_signed(
+    req: &mut ServiceRequest,
+    signature: auth::signed::Signature,
+    keys: &auth::Keyset,
+    nonces: &auth::signed::Nonces,
+    body_limit: usize,
+) -> Result<auth::Key, HttpResponse> {
+    let now = chrono::Utc::now().timestamp();
+    if !signature.fresh(now) {
+        return Err(unauthorized("the signature has expired"));
+    }
+
+    let mut payload = req.take_payload();
+    let mut body = web::BytesMut::new();
+    while let Some(chunk) = payload.next().await {
+        let chunk = chunk.map_err(|e| HttpResponse::BadRequest().body(e.to_string()))?;
+        if body.len() + chunk.len() > body_limit {
+            return Err(HttpResponse::PayloadTooLarge().finish());
+        }
+        body.extend_from_slice(&chunk);
+    }
+    let body = body.freeze();
+
+    let path = req
+        .uri()
+        .path_and_query()
+        .map_or(req.path(), |path| path.as_str());
+    let key = keys
+        .verify(&signature, req.method().as_str(), path, &body)
+        .cloned()
+        .ok_or_else(|| unauthorized("bad signature"))?;
+
+    match nonces.insert(&signature, now) {
+        Ok(()) => {}
+        Err(auth::signed::NonceError::Reused) => {
+            return Err(unauthorized("the nonce was already used"));
+        }
+        Err(auth::signed::NonceError::Full) => {
+            return Err(HttpResponse::TooManyRequests().body("Too many signed requests"));
+        }
+    }
+
+    req.set_payload(body.into());
+    Ok(key)
 }
 
 pub struct AuthMiddleware {
-    key: String,
+    keys: Arc<auth::Keyset>,
+    peers: Arc<socket::Policy>,
+    nonces: Arc<auth::signed::Nonces>,
+    require_signed: bool,
+    /// Signed request bodies are read before the handler runs, so they're
+    /// capped here instead.
+    body_limit: usize,
 }
 
 impl AuthMiddleware {
-    pub fn new(key: String) -> Self {
-        Self { key }
+    pub fn new(
+        keys: Arc<auth::Keyset>,
+        peers: Arc<socket::Policy>,
+        nonces: Arc<auth::signed::Nonces>,
+        require_signed: bool,
+        body_limit: usize,
+    ) -> Self {
+        Self {
+            keys,
+            peers,
+            nonces,
+            require_signed,
+            body_limit,
+        }
     }
 }
 
@@ -169,14 +894,22 @@ where
     fn new_transform(&self, service: S) -> Self::Future {
         ok(AuthMiddlewareService {
             service: Rc::new(service),
-            key: self.key.clone(),
+            keys: Arc::clone(&self.keys),
+            peers: Arc::clone(&self.peers),
+            nonces: Arc::clone(&self.nonces),
+            require_signed: self.require_signed,
+            body_limit: self.body_limit,
         })
     }
 }
 
 pub struct AuthMiddlewareService<S> {
     service: Rc<S>,
-    key: String,
+    keys: Arc<auth::Keyset>,
+    peers: Arc<socket::Policy>,
+    nonces: Arc<auth::signed::Nonces>,
+    require_signed: bool,
+    body_limit: usize,
 }
 
 impl<S, B> Service<ServiceRequest> for AuthMiddlewareService<S>
@@ -195,23 +928,64 @@ where
         self.service.poll_ready(ctx)
     }
 
-    fn call(&self, req: ServiceRequest) -> Self::Future {
-        let key = self.key.clone();
+    fn call(&self, mut req: ServiceRequest) -> Self::Future {
+        let keys = Arc::clone(&self.keys);
+        let peers = Arc::clone(&self.peers);
+        let nonces = Arc::clone(&self.nonces);
+        let require_signed = self.require_signed;
+        let body_limit = self.body_limit;
         let srv = self.service.clone();
         Box::pin(async move {
-            let authorized = req
+            let header = req
                 .headers()
                 .get(actix_web::http::header::AUTHORIZATION)
                 .and_then(|h| h.to_str().ok())
-                .map(|v| v == key)
-                .unwrap_or(false);
-            if !authorized {
+                .map(str::to_string);
+            let signature = header.as_deref().and_then(auth::signed::Signature::parse);
+            let key = match signature {
+                Some(signature) => {
+                    match verify_signed(&mut req, signature, &keys, &nonces, body_limit).await {
+                        Ok(key) => Some(key),
+                        Err(response) => {
+                            return Ok(req.into_response(response.map_into_boxed_body()));
+                        }
+                    }
+                }
+                None if require_signed => None,
+                None => header.and_then(|v| keys.authenticate(&v).cloned()),
+            };
+            let key = key.or_else(|| {
+                req.conn_data::<tls::ClientCertificate>()
+                    .and_then(|cert| keys.for_certificate(cert))
+                    .cloned()
+            });
+            let key = key.or_else(|| {
+                req.conn_data::<socket::Peer>()
+                    .and_then(|peer| peers.key_for(peer))
+            });
+            let Some(key) = key else {
                 return Ok(req.into_response(
                     HttpResponse::Unauthorized()
                         .body("Unauthorized")
                         .map_into_boxed_body(),
                 ));
+            };
+            let Some(scope) = auth::Scope::required(req.method(), req.match_info().as_str()) else {
+                return Ok(
+                    req.into_response(HttpResponse::NotFound().finish().map_into_boxed_body())
+                );
+            };
+            if !key.allows(scope) {
+                log::info!(
+                    "Key {} lacks {} for {} {}",
+                    key.name,
+                    scope.as_str(),
+                    req.method(),
+                    req.path()
+                );
+                return Ok(req.into_response(forbidden(scope).map_into_boxed_body()));
             }
+            req.extensions_mut().insert(key);
             let res = srv.call(req).await?;
             Ok(res.map_into_boxed_body())
         })
@@ -226,6 +1000,109 @@ struct Cli {
 
     #[arg(short, long, action = clap::ArgAction::Count)]
     quiet: u8,
+
+    /// Config file [default: $XDG_CONFIG_HOME/mox/moxapi/config.yaml]
+    #[arg(long)]
+    config: Option<PathBuf>,
+
+    /// Address to listen on, repeatable [default: 0.0.0.0]
+    #[arg(long)]
+    listen: Vec<IpAddr>,
+
+    /// Port to listen on [default: 8000]
+    #[arg(long)]
+    port: Option<u16>,
+
+    /// Don't listen on TCP at all, only on --socket
+    #[arg(long)]
+    no_tcp: bool,
+
+    /// Unix socket to listen on as well, e.g. $XDG_RUNTIME_DIR/moxapi.sock
+    #[arg(long)]
+    socket: Option<PathBuf>,
+
+    /// Worker threads [default: 2]
+    #[arg(long)]
+    workers: Option<usize>,
+
+    /// Origin browsers may call the API from, repeatable [default: any]
+    #[arg(long)]
+    cors_origin: Vec<String>,
+
+    /// Largest request body in bytes [default: 1048576]
+    #[arg(long)]
+    payload_limit: Option<usize>,
+
+    /// Act on the graphical session of this user (name or uid) by default
+    #[arg(long)]
+    user: Option<String>,
+
+    /// Only consider sessions on this seat
+    #[arg(long)]
+    seat: Option<String>,
+
+    /// Where scheduled jobs are stored [default: $XDG_STATE_HOME/moxapi/schedule.json]
+    #[arg(long)]
+    schedule_file: Option<PathBuf>,
+
+    /// File of named API keys and their scopes, used instead of AUTH_KEY [env: AUTH_KEYS_FILE]
+    #[arg(long)]
+    keys_file: Option<PathBuf>,
+
+    /// Only accept signed requests, never a bare API key
+    #[arg(long)]
+    require_signed: bool,
+
+    /// Serve HTTPS with this PEM certificate chain, reloaded on SIGHUP
+    #[arg(long)]
+    tls_cert: Option<PathBuf>,
+
+    /// PEM private key for --tls-cert
+    #[arg(long)]
+    tls_key: Option<PathBuf>,
+
+    /// Accept client certificates signed by this PEM CA, matched to keys by fingerprint
+    #[arg(long)]
+    tls_client_ca: Option<PathBuf>,
+}
+
+impl Cli {
+    /// The settings given on the command line, which win over every other
+    /// source.
+    fn overrides(&self) -> Vec<(&'static str, config::Value)> {
+        let path = |path: &Option<PathBuf>| {
+            path.as_ref()
+                .map(|path| path.to_string_lossy().into_owned().into())
+        };
+        let list = |values: Vec<String>| (!values.is_empty()).then(|| values.into());
+
+        [
+            (
+                "listen",
+                list(self.listen.iter().map(IpAddr::to_string).collect()),
+            ),
+            ("listen", self.no_tcp.then(|| Vec::<String>::new().into())),
+            ("socket", path(&self.socket)),
+            ("port", self.port.map(Into::into)),
+            ("workers", self.workers.map(|n| (n as u64).into())),
+            ("cors_origins", list(self.cors_origin.clone())),
+            (
+                "payload_limit",
+                self.payload_limit.map(|n| (n as u64).into()),
+            ),
+            ("user", self.user.clone().map(Into::into)),
+            ("seat", self.seat.clone().map(Into::into)),
+            ("schedule_file", path(&self.schedule_file)),
+            ("auth_keys_file", path(&self.keys_file)),
+            ("require_signed", self.require_signed.then(|| true.into())),
+            ("tls.cert", path(&self.tls_cert)),
+            ("tls.key", path(&self.tls_key)),
+            ("tls.client_ca", path(&self.tls_client_ca)),
+        ]
+        .into_iter()
+        .filter_map(|(key, value)| Some((key, value?)))
+        .collect()
+    }
 }
 
 #[actix_web::main]
@@ -253,26 +1130,152 @@ async fn main() -> std::io::Result<()> {
         };
     });
 
-    Builder::new().filter(Some("daemon"), log_level).init();
+    Builder::new()
+        .filter(Some(env!("CARGO_CRATE_NAME")), log_level)
+        .init();
+
+    let settings = settings::Settings::load(cli.config.as_deref(), cli.overrides())
+        .unwrap_or_else(|e| panic!("{e:#}"));
+    log::info!(
+        "Effective config:\n{}",
+        serde_json::to_string_pretty(&settings).unwrap()
+    );
+
+    let events = events::EventBus::new();
+    let notify = notify::NotificationManager::new().await.unwrap();
+    events.relay_notifications(notify.subscribe());
+    let idle = idle::Idle::new(
+        idle::SessionTarget {
+            user: settings.user.clone(),
+            seat: settings.seat.clone(),
+        },
+        events.clone(),
+    )
+    .await
+    .unwrap();
+    if let Err(e) = idle.watch().await {
+        log::warn!("Failed to watch session signals: {e}");
+    }
+    let power = power::Power::new(idle.login_manager());
+    let idle = Arc::new(RwLock::new(idle));
+    let scheduler = scheduler::Scheduler::new(
+        settings
+            .schedule_file
+            .clone()
+            .unwrap_or_else(scheduler::default_path),
+        Arc::clone(&idle),
+        power.clone(),
+        notify.clone(),
+    );
+    tokio::spawn(scheduler.clone().run());
 
     let state = web::Data::new(State {
-        idle: Arc::new(RwLock::new(idle::Idle::new().await.unwrap())),
-        notify: notify::NotificationManager::new().await.unwrap(),
+        events,
+        idle,
+        progress: notify::progress::ProgressManager::new(notify.clone()),
+        notify,
+        power,
+        scheduler,
+    });
+
+    let idle = Arc::clone(&state.idle);
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(1));
+        loop {
+            interval.tick().await;
+            let expired = idle.read().await.has_expired_holds();
+            if expired && let Err(e) = idle.write().await.release_expired().await {
+                log::warn!("Failed to release expired inhibitors: {e}");
+            }
+        }
     });
 
-    let auth_key = match env::var("AUTH_KEY_FILE") {
-        Ok(auth_key_file) => std::fs::read_to_string(&auth_key_file)
-            .unwrap_or_else(|_| panic!("Failed to read {auth_key_file}")),
-        Err(_) => env::var("AUTH_KEY").expect("AUTH_KEY_FILE or AUTH_KEY env var must be set"),
+    let keys = Arc::new(
+        match (
+            &settings.auth_keys_file,
+            &settings.auth_key_file,
+            &settings.auth_key,
+        ) {
+            (Some(keys_file), _, _) => {
+                auth::Keyset::load(keys_file).unwrap_or_else(|e| panic!("{e:#}"))
+            }
+            (None, Some(key_file), _) => auth::Keyset::single(
+                &std::fs::read_to_string(key_file)
+                    .unwrap_or_else(|_| panic!("Failed to read {}", key_file.display())),
+            ),
+            (None, None, Some(key)) => auth::Keyset::single(key),
+            // Only local users can reach the node, so it needs no keys.
+            (None, None, None) if settings.listen.is_empty() => auth::Keyset::default(),
+            (None, None, None) => {
+                panic!("Set auth_keys_file, auth_key_file or auth_key, e.g. with AUTH_KEY")
+            }
+        },
+    );
+
+    let nonces = Arc::new(auth::signed::Nonces::default());
+
+    let tls_config = match &settings.tls {
+        Some(tls_settings) => {
+            let certificates = Arc::new(
+                tls::Certificates::load(&tls_settings.cert, &tls_settings.key)
+                    .unwrap_or_else(|e| panic!("{e:#}")),
+            );
+            let reloaded = Arc::clone(&certificates);
+            tokio::spawn(async move {
+                let mut hangup =
+                    tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup())
+                        .expect("Failed to listen for SIGHUP");
+                while hangup.recv().await.is_some() {
+                    match reloaded.reload() {
+                        Ok(()) => log::info!("Reloaded the TLS certificate"),
+                        Err(e) => log::warn!("Kept the old TLS certificate: {e:#}"),
+                    }
+                }
+            });
+            Some(
+                tls::server_config(certificates, tls_settings.client_ca.as_deref())
+                    .unwrap_or_else(|e| panic!("{e:#}")),
+            )
+        }
+        None => None,
+    };
+
+    let (listener, peers) = match &settings.socket {
+        Some(path) => {
+            let mode = socket::parse_mode(&settings.socket_mode).unwrap_or_else(|| {
+                panic!(
+                    "Invalid socket_mode {}, use octal like 0660",
+                    settings.socket_mode
+                )
+            });
+            let (listener, owner) = socket::bind(path, mode)?;
+            (
+                Some(listener),
+                socket::Policy::new(&settings.socket_peers, owner),
+            )
+        }
+        None => (None, socket::Policy::default()),
     };
+    let peers = Arc::new(peers);
+    if listener.is_none() && settings.listen.is_empty() {
+        panic!("Nothing to listen on. Set listen or socket");
+    }
 
-    HttpServer::new(move || {
+    let settings = Arc::new(settings);
+    let app_settings = Arc::clone(&settings);
+    let mut server = HttpServer::new(move || {
+        let settings = &app_settings;
         App::new()
-            .wrap(AuthMiddleware::new(auth_key.clone()))
+            .wrap(AuthMiddleware::new(
+                Arc::clone(&keys),
+                Arc::clone(&peers),
+                Arc::clone(&nonces),
+                settings.require_signed,
+                settings.payload_limit,
+            ))
             .wrap(
-                Cors::default()
-                    .allow_any_origin()
-                    .allowed_methods(vec!["GET", "POST", "OPTIONS"])
+                cors(&settings.cors_origins)
+                    .allowed_methods(vec!["GET", "POST", "PATCH", "DELETE", "OPTIONS"])
                     .allowed_headers(vec![
                         actix_web::http::header::AUTHORIZATION,
                         actix_web::http::header::ACCEPT,
@@ -282,8 +1285,8 @@ async fn main() -> std::io::Result<()> {
                     .max_age(3600),
             )
             .app_data(state.clone())
-            .app_data(web::PayloadConfig::new(1024 * 1024))
-            .app_data(web::JsonConfig::default().limit(1024 * 1024))
+            .app_data(web::PayloadConfig::new(settings.payload_limit))
+            .app_data(web::JsonConfig::default().limit(settings.json_limit))
             .wrap(
                 DefaultHeaders::new()
                     .add(("X-Content-Type-Options", "nosniff"))
@@ -294,14 +1297,58 @@ async fn main() -> std::io::Result<()> {
             .service(post_idle_lock)
             .service(post_idle_unlock)
             .service(post_simulate_user_activity)
+            .service(get_idle_inhibit)
             .service(post_idle_inhibit)
+            .service(post_idle_inhibit_logind)
+            .service(delete_idle_inhibit)
             .service(post_idle_uninhibit)
+            .service(get_power)
+            .service(post_power)
+            .service(get_schedule)
+            .service(post_schedule)
+            .service(delete_schedule)
             .service(get_notify_capabilities)
             .service(post_notify)
+            .service(get_notify)
+            .service(delete_notify)
+            .service(get_notify_progress)
+            .service(post_notify_progress)
+            .service(patch_notify_progress)
+            .service(post_notify_progress_finish)
+            .service(get_notify_wait)
+            .service(get_notify_events)
+            .service(get_events)
             .service(get_status)
+            .service(get_sessions)
     })
-    .bind(("0.0.0.0", 8000))?
-    .workers(2)
-    .run()
-    .await
+    .on_connect(|conn, ext| {
+        tls::on_connect(conn, ext);
+        socket::on_connect(conn, ext);
+    });
+
+    for ip in &settings.listen {
+        let addr = (*ip, settings.port);
+        server = match &tls_config {
+            Some(tls_config) => server.bind_rustls_0_23(addr, tls_config.clone())?,
+            None => server.bind(addr)?,
+        };
+    }
+    if let Some(listener) = listener {
+        server = server.listen_uds(listener)?;
+    }
+    let result = server.workers(settings.workers).run().await;
+    if let Some(path) = &settings.socket {
+        socket::unbind(path);
+    }
+    result
+}
+
+fn cors(origins: &[String]) -> Cors {
+    if origins.is_empty() {
+        return Cors::default().allow_any_origin();
+    }
+
+    origins
+        .iter()
+        .fold(Cors::default(), |cors, origin| cors.allowed_origin(origin))
 }
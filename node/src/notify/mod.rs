@@ -1,4 +1,20 @@
-use std::collections::HashMap;
+pub mod progress;
+
+use futures_util::StreamExt;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tokio::sync::broadcast;
+use zbus::zvariant::{Structure, Value};
+
+/// How many notifications' worth of events are kept for late waiters.
+const EVENT_HISTORY: usize = 256;
+
+/// Upper bound on notifications remembered in the sent registry.
+const SENT_HISTORY: usize = 1024;
 
 #[zbus::proxy(
     interface = "org.freedesktop.Notifications",
@@ -20,22 +36,318 @@ trait Notifications {
         hints: HashMap<&str, zbus::zvariant::Value<'_>>,
         expire_timeout: i32,
     ) -> zbus::Result<u32>;
+
+    async fn close_notification(&self, id: u32) -> zbus::Result<()>;
+
+    #[zbus(signal)]
+    fn action_invoked(&self, id: u32, action_key: &str) -> zbus::Result<()>;
+
+    #[zbus(signal)]
+    fn notification_closed(&self, id: u32, reason: u32) -> zbus::Result<()>;
+
+    #[zbus(signal)]
+    fn activation_token(&self, id: u32, activation_token: &str) -> zbus::Result<()>;
+}
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum CloseReason {
+    Expired,
+    Dismissed,
+    ClosedByCall,
+    Undefined,
+}
+
+impl From<u32> for CloseReason {
+    fn from(reason: u32) -> Self {
+        match reason {
+            1 => Self::Expired,
+            2 => Self::Dismissed,
+            3 => Self::ClosedByCall,
+            _ => Self::Undefined,
+        }
+    }
+}
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum NotificationState {
+    Open,
+    ClosedByUser,
+    Expired,
+    ClosedByApi,
+    Closed,
+}
+
+impl From<CloseReason> for NotificationState {
+    fn from(reason: CloseReason) -> Self {
+        match reason {
+            CloseReason::Expired => Self::Expired,
+            CloseReason::Dismissed => Self::ClosedByUser,
+            CloseReason::ClosedByCall => Self::ClosedByApi,
+            CloseReason::Undefined => Self::Closed,
+        }
+    }
+}
+
+/// A notification sent through this node, as tracked in the registry.
+#[derive(Serialize, Clone, Debug)]
+pub struct SentNotification {
+    pub id: u32,
+    pub summary: Box<str>,
+    pub sent_at: u64,
+    pub caller: Box<str>,
+    pub state: NotificationState,
+}
+
+#[derive(Default)]
+struct Registry {
+    notifications: HashMap<u32, SentNotification>,
+}
+
+impl Registry {
+    fn insert(&mut self, notification: SentNotification) {
+        if self.notifications.len() >= SENT_HISTORY
+            && !self.notifications.contains_key(&notification.id)
+        {
+            let oldest = self
+                .notifications
+                .values()
+                .min_by_key(|n| (n.state == NotificationState::Open, n.sent_at))
+                .map(|n| n.id);
+            if let Some(oldest) = oldest {
+                self.notifications.remove(&oldest);
+            }
+        }
+        self.notifications.insert(notification.id, notification);
+    }
+
+    fn set_state(&mut self, id: u32, state: NotificationState) {
+        if let Some(notification) = self.notifications.get_mut(&id) {
+            notification.state = state;
+        }
+    }
+}
+
+#[derive(Serialize, Clone, Debug)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum NotificationEvent {
+    ActionInvoked { id: u32, action_key: Box<str> },
+    ActivationToken { id: u32, token: Box<str> },
+    Closed { id: u32, reason: CloseReason },
 }
 
+impl NotificationEvent {
+    pub fn id(&self) -> u32 {
+        match self {
+            Self::ActionInvoked { id, .. }
+            | Self::ActivationToken { id, .. }
+            | Self::Closed { id, .. } => *id,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::ActionInvoked { .. } => "action_invoked",
+            Self::ActivationToken { .. } => "activation_token",
+            Self::Closed { .. } => "closed",
+        }
+    }
+
+    /// Whether the user has answered the notification, one way or another.
+    fn is_final(&self) -> bool {
+        !matches!(self, Self::ActivationToken { .. })
+    }
+}
+
+#[derive(Default)]
+struct EventLog {
+    events: HashMap<u32, Vec<NotificationEvent>>,
+    order: VecDeque<u32>,
+}
+
+impl EventLog {
+    fn push(&mut self, event: NotificationEvent) {
+        let id = event.id();
+        if !self.events.contains_key(&id) {
+            if self.order.len() == EVENT_HISTORY
+                && let Some(oldest) = self.order.pop_front()
+            {
+                self.events.remove(&oldest);
+            }
+            self.order.push_back(id);
+        }
+        self.events.entry(id).or_default().push(event);
+    }
+
+    fn answered(&self, id: u32) -> Option<Box<[NotificationEvent]>> {
+        let events = self.events.get(&id)?;
+        events
+            .iter()
+            .any(NotificationEvent::is_final)
+            .then(|| events.clone().into_boxed_slice())
+    }
+}
+
+#[derive(Deserialize, Clone, Copy, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum Urgency {
+    Low = 0,
+    Normal = 1,
+    Critical = 2,
+}
+
+/// Raw image for the `image-data` hint, laid out as the spec's `(iiibiiay)`.
+#[derive(Deserialize, Clone, Debug)]
+pub struct ImageData {
+    pub width: i32,
+    pub height: i32,
+    pub rowstride: i32,
+    pub has_alpha: bool,
+    pub bits_per_sample: i32,
+    pub channels: i32,
+    pub data: Vec<u8>,
+}
+
+#[derive(Clone)]
 pub struct NotificationManager {
-    session_conn: zbus::Connection,
+    _session_conn: zbus::Connection,
     proxy: NotificationsProxy<'static>,
+    events: broadcast::Sender<NotificationEvent>,
+    log: Arc<Mutex<EventLog>>,
+    registry: Arc<Mutex<Registry>>,
 }
 
 impl NotificationManager {
     pub async fn new() -> anyhow::Result<Self> {
         let session_conn = zbus::Connection::session().await?;
         let proxy = NotificationsProxy::new(&session_conn).await?;
+        let (events, _) = broadcast::channel(64);
+        let log = Arc::new(Mutex::new(EventLog::default()));
+        let registry = Arc::new(Mutex::new(Registry::default()));
+
+        let mut action_invoked = proxy.receive_action_invoked().await?;
+        let mut activation_token = proxy.receive_activation_token().await?;
+        let mut notification_closed = proxy.receive_notification_closed().await?;
+
+        let tx = events.clone();
+        let event_log = Arc::clone(&log);
+        let sent = Arc::clone(&registry);
+        tokio::spawn(async move {
+            loop {
+                let event = tokio::select! {
+                    Some(signal) = action_invoked.next() => signal.args().map(|args| {
+                        NotificationEvent::ActionInvoked {
+                            id: args.id,
+                            action_key: args.action_key.into(),
+                        }
+                    }),
+                    Some(signal) = activation_token.next() => signal.args().map(|args| {
+                        NotificationEvent::ActivationToken {
+                            id: args.id,
+                            token: args.activation_token.into(),
+                        }
+                    }),
+                    Some(signal) = notification_closed.next() => signal.args().map(|args| {
+                        NotificationEvent::Closed {
+                            id: args.id,
+                            reason: args.reason.into(),
+                        }
+                    }),
+                    else => break,
+                };
+
+                match event {
+                    Ok(event) => {
+                        // Other applications' notifications are none of our
+                        // callers' business.
+                        let mut sent = sent.lock().unwrap();
+                        if !sent.notifications.contains_key(&event.id()) {
+                            continue;
+                        }
+                        if let NotificationEvent::Closed { id, reason } = event {
+                            sent.set_state(id, reason.into());
+                        }
+                        drop(sent);
+                        event_log.lock().unwrap().push(event.clone());
+                        _ = tx.send(event);
+                    }
+                    Err(e) => log::warn!("Malformed notification signal: {e}"),
+                }
+            }
+        });
 
         Ok(Self {
-            session_conn,
+            _session_conn: session_conn,
             proxy,
+            events,
+            log,
+            registry,
+        })
+    }
+
+    /// Lists every notification this node has sent, oldest first.
+    pub fn sent(&self) -> Box<[SentNotification]> {
+        let mut sent: Vec<_> = self
+            .registry
+            .lock()
+            .unwrap()
+            .notifications
+            .values()
+            .cloned()
+            .collect();
+        sent.sort_by_key(|n| (n.sent_at, n.id));
+        sent.into_boxed_slice()
+    }
+
+    /// Closes a notification previously sent by this node. Returns `false`
+    /// if the id is unknown to the registry.
+    pub async fn close(&self, id: u32) -> anyhow::Result<bool> {
+        if !self
+            .registry
+            .lock()
+            .unwrap()
+            .notifications
+            .contains_key(&id)
+        {
+            return Ok(false);
+        }
+
+        self.proxy.close_notification(id).await?;
+        self.registry
+            .lock()
+            .unwrap()
+            .set_state(id, NotificationState::ClosedByApi);
+
+        Ok(true)
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<NotificationEvent> {
+        self.events.subscribe()
+    }
+
+    /// Waits until notification `id` is either acted upon or closed and
+    /// returns every event seen for it. Returns immediately if that already
+    /// happened, or `None` once `timeout` elapses.
+    pub async fn wait(&self, id: u32, timeout: Duration) -> Option<Box<[NotificationEvent]>> {
+        let mut rx = self.events.subscribe();
+        if let Some(events) = self.log.lock().unwrap().answered(id) {
+            return Some(events);
+        }
+
+        tokio::time::timeout(timeout, async {
+            loop {
+                match rx.recv().await {
+                    Ok(event) if event.id() == id && event.is_final() => break,
+                    Err(broadcast::error::RecvError::Closed) => break,
+                    _ => {}
+                }
+            }
         })
+        .await
+        .ok()?;
+
+        self.log.lock().unwrap().answered(id)
     }
 
     pub async fn get_capabilities(&self) -> anyhow::Result<Box<[Box<str>]>> {
@@ -45,26 +357,48 @@ impl NotificationManager {
             .map_err(|_| anyhow::anyhow!(""))
     }
 
-    pub async fn builder(&self) -> NotificationBuilder {
+    pub async fn builder(&self) -> NotificationBuilder<'_> {
         NotificationBuilder {
+            app_name: "moxapi",
+            app_icon: "",
             summary: "",
             body: "",
             proxy: self.proxy.clone(),
+            registry: Arc::clone(&self.registry),
+            caller: "",
             id: 0,
             timeout: 0,
+            actions: Vec::new(),
+            hints: HashMap::new(),
         }
     }
 }
 
 pub struct NotificationBuilder<'a> {
     proxy: NotificationsProxy<'static>,
+    registry: Arc<Mutex<Registry>>,
+    caller: &'a str,
+    app_name: &'a str,
+    app_icon: &'a str,
     summary: &'a str,
     body: &'a str,
     timeout: i32,
     id: u32,
+    actions: Vec<&'a str>,
+    hints: HashMap<&'static str, Value<'a>>,
 }
 
 impl<'a> NotificationBuilder<'a> {
+    pub fn with_app_name(mut self, app_name: &'a str) -> Self {
+        self.app_name = app_name;
+        self
+    }
+
+    pub fn with_app_icon(mut self, app_icon: &'a str) -> Self {
+        self.app_icon = app_icon;
+        self
+    }
+
     pub fn with_summary(mut self, summary: &'a str) -> Self {
         self.summary = summary;
         self
@@ -85,19 +419,130 @@ impl<'a> NotificationBuilder<'a> {
         self
     }
 
+    /// Who asked for the notification, recorded in the sent registry.
+    pub fn with_caller(mut self, caller: &'a str) -> Self {
+        self.caller = caller;
+        self
+    }
+
+    /// Adds an action button. Use the key `"default"` for the action invoked
+    /// when the notification itself is clicked.
+    pub fn with_action(mut self, key: &'a str, label: &'a str) -> Self {
+        self.actions.push(key);
+        self.actions.push(label);
+        self
+    }
+
+    /// Interpret action keys as icon names instead of showing the labels.
+    pub fn with_action_icons(mut self, action_icons: bool) -> Self {
+        self.hints.insert("action-icons", Value::from(action_icons));
+        self
+    }
+
+    pub fn with_urgency(mut self, urgency: Urgency) -> Self {
+        self.hints.insert("urgency", Value::from(urgency as u8));
+        self
+    }
+
+    pub fn with_category(mut self, category: &'a str) -> Self {
+        self.hints.insert("category", Value::from(category));
+        self
+    }
+
+    pub fn with_desktop_entry(mut self, desktop_entry: &'a str) -> Self {
+        self.hints
+            .insert("desktop-entry", Value::from(desktop_entry));
+        self
+    }
+
+    pub fn with_image_data(mut self, image: ImageData) -> Self {
+        let structure = Structure::from((
+            image.width,
+            image.height,
+            image.rowstride,
+            image.has_alpha,
+            image.bits_per_sample,
+            image.channels,
+            image.data,
+        ));
+        self.hints.insert("image-data", Value::from(structure));
+        self
+    }
+
+    pub fn with_image_path(mut self, image_path: &'a str) -> Self {
+        self.hints.insert("image-path", Value::from(image_path));
+        self
+    }
+
+    /// Keep the notification around after an action is invoked.
+    pub fn with_resident(mut self, resident: bool) -> Self {
+        self.hints.insert("resident", Value::from(resident));
+        self
+    }
+
+    pub fn with_sound_file(mut self, sound_file: &'a str) -> Self {
+        self.hints.insert("sound-file", Value::from(sound_file));
+        self
+    }
+
+    pub fn with_sound_name(mut self, sound_name: &'a str) -> Self {
+        self.hints.insert("sound-name", Value::from(sound_name));
+        self
+    }
+
+    pub fn with_suppress_sound(mut self, suppress_sound: bool) -> Self {
+        self.hints
+            .insert("suppress-sound", Value::from(suppress_sound));
+        self
+    }
+
+    /// Bypass the server's persistence capability, if any.
+    pub fn with_transient(mut self, transient: bool) -> Self {
+        self.hints.insert("transient", Value::from(transient));
+        self
+    }
+
+    /// Screen position the notification should point to.
+    pub fn with_position(mut self, x: i32, y: i32) -> Self {
+        self.hints.insert("x", Value::from(x));
+        self.hints.insert("y", Value::from(y));
+        self
+    }
+
+    /// Progress bar value in percent. Not part of the spec, but understood
+    /// by most notification daemons.
+    pub fn with_value(mut self, value: i32) -> Self {
+        self.hints.insert("value", Value::from(value));
+        self
+    }
+
     pub async fn send(self) -> anyhow::Result<u32> {
-        self.proxy
+        let id = self
+            .proxy
             .notify(
-                "moxapi",
+                self.app_name,
                 self.id,
-                "",
+                self.app_icon,
                 self.summary,
                 self.body,
-                Box::new([]),
-                HashMap::new(),
+                self.actions.into_boxed_slice(),
+                self.hints,
                 self.timeout,
             )
-            .await
-            .map_err(|e| anyhow::anyhow!("{e}"))
+            .await?;
+
+        let sent_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+        self.registry.lock().unwrap().insert(SentNotification {
+            id,
+            summary: self.summary.into(),
+            sent_at,
+            caller: self.caller.into(),
+            state: NotificationState::Open,
+        });
+
+        Ok(id)
     }
 }
@@ -0,0 +1,269 @@
+use super::{NotificationManager, Urgency};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    sync::{
+        Arc,
+        atomic::{AtomicU32, Ordering},
+    },
+    time::{Duration, Instant},
+};
+use tokio::sync::Mutex;
+
+/// Updates arriving faster than this are coalesced before reaching the daemon.
+const MIN_UPDATE_INTERVAL: Duration = Duration::from_millis(500);
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum FinalState {
+    Completed,
+    Failed,
+    Cancelled,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct Progress {
+    pub id: u32,
+    pub notification_id: u32,
+    pub summary: Box<str>,
+    pub body: Box<str>,
+    pub value: u8,
+    /// An update is waiting for the rate limit before being sent.
+    pub pending: bool,
+    #[serde(skip)]
+    caller: Box<str>,
+    #[serde(skip)]
+    last_sent: Option<Instant>,
+    /// The final state is being sent, so updates are dropped.
+    #[serde(skip)]
+    finishing: bool,
+    /// Held while a notification for this bar is on its way, so sends go out
+    /// one at a time and nothing overtakes the final state.
+    #[serde(skip)]
+    sending: Arc<Mutex<()>>,
+}
+
+/// Server-side bookkeeping for progress notifications, so clients only ever
+/// deal with a stable progress id while the node keeps replacing the
+/// underlying notification.
+#[derive(Clone)]
+pub struct ProgressManager {
+    notify: NotificationManager,
+    bars: Arc<Mutex<HashMap<u32, Progress>>>,
+    next_id: Arc<AtomicU32>,
+}
+
+impl ProgressManager {
+    pub fn new(notify: NotificationManager) -> Self {
+        Self {
+            notify,
+            bars: Arc::new(Mutex::new(HashMap::new())),
+            next_id: Arc::new(AtomicU32::new(1)),
+        }
+    }
+
+    pub async fn list(&self) -> Box<[Progress]> {
+        let mut bars: Vec<_> = self.bars.lock().await.values().cloned().collect();
+        bars.sort_by_key(|p| p.id);
+        bars.into_boxed_slice()
+    }
+
+    pub async fn create(
+        &self,
+        summary: &str,
+        body: &str,
+        value: u8,
+        caller: &str,
+    ) -> anyhow::Result<Progress> {
+        let mut progress = Progress {
+            id: self.next_id.fetch_add(1, Ordering::Relaxed),
+            notification_id: 0,
+            summary: summary.into(),
+            body: body.into(),
+            value: value.min(100),
+            pending: false,
+            caller: caller.into(),
+            last_sent: None,
+            finishing: false,
+            sending: Arc::default(),
+        };
+        progress.notification_id = self.send(&progress, 0, None).await?;
+        progress.last_sent = Some(Instant::now());
+        self.bars.lock().await.insert(progress.id, progress.clone());
+
+        Ok(progress)
+    }
+
+    /// Applies an update, sending it right away unless the previous one went
+    /// out less than [`MIN_UPDATE_INTERVAL`] ago. Returns `None` for an
+    /// unknown id.
+    pub async fn update(
+        &self,
+        id: u32,
+        value: Option<u8>,
+        body: Option<&str>,
+    ) -> anyhow::Result<Option<Progress>> {
+        // The lock isn't held while sending, so a slow bus only holds up the
+        // update that's waiting on it.
+        let (progress, sending) = {
+            let mut bars = self.bars.lock().await;
+            let Some(progress) = bars.get_mut(&id) else {
+                return Ok(None);
+            };
+            if progress.finishing {
+                return Ok(Some(progress.clone()));
+            }
+
+            if let Some(value) = value {
+                progress.value = value.min(100);
+            }
+            if let Some(body) = body {
+                progress.body = body.into();
+            }
+
+            let elapsed = progress.last_sent.map(|t| t.elapsed());
+            match elapsed {
+                Some(elapsed) if elapsed < MIN_UPDATE_INTERVAL => {
+                    if !progress.pending {
+                        progress.pending = true;
+                        self.schedule_flush(id, MIN_UPDATE_INTERVAL - elapsed);
+                    }
+                    return Ok(Some(progress.clone()));
+                }
+                _ => (start_send(progress), Arc::clone(&progress.sending)),
+            }
+        };
+
+        Ok(Some(
+            self.send_update(&progress, &sending)
+                .await?
+                .unwrap_or(progress),
+        ))
+    }
+
+    /// Replaces the progress notification with its final state and stops
+    /// tracking it once that's been sent, so a failed attempt can be retried.
+    pub async fn finish(
+        &self,
+        id: u32,
+        state: FinalState,
+        body: Option<&str>,
+    ) -> anyhow::Result<Option<Progress>> {
+        let sending = {
+            let mut bars = self.bars.lock().await;
+            let Some(progress) = bars.get_mut(&id).filter(|p| !p.finishing) else {
+                return Ok(None);
+            };
+            // Neither a waiting update nor one that's already on its way may
+            // replace the final state.
+            progress.finishing = true;
+            progress.pending = false;
+            Arc::clone(&progress.sending)
+        };
+        let _sending = sending.lock().await;
+        let Some(mut progress) = self.bars.lock().await.get(&id).cloned() else {
+            return Ok(None);
+        };
+
+        if let Some(body) = body {
+            progress.body = body.into();
+        }
+        let urgency = match state {
+            FinalState::Completed => {
+                progress.value = 100;
+                None
+            }
+            FinalState::Failed => Some(Urgency::Critical),
+            FinalState::Cancelled => None,
+        };
+        match self.send(&progress, -1, urgency).await {
+            Ok(notification_id) => progress.notification_id = notification_id,
+            Err(e) => {
+                if let Some(progress) = self.bars.lock().await.get_mut(&id) {
+                    progress.finishing = false;
+                }
+                return Err(e);
+            }
+        }
+        self.bars.lock().await.remove(&id);
+
+        Ok(Some(progress))
+    }
+
+    fn schedule_flush(&self, id: u32, delay: Duration) {
+        let this = self.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(delay).await;
+            let (progress, sending) = {
+                let mut bars = this.bars.lock().await;
+                let Some(progress) = bars.get_mut(&id).filter(|p| p.pending) else {
+                    return;
+                };
+                (start_send(progress), Arc::clone(&progress.sending))
+            };
+
+            if let Err(e) = this.send_update(&progress, &sending).await {
+                log::warn!("Failed to update progress notification {id}: {e}");
+            }
+        });
+    }
+
+    /// Sends an update once the bar's previous send is done, unless the bar
+    /// started finishing meanwhile. Returns the progress as tracked after the
+    /// update went out, or `None` if it wasn't sent.
+    async fn send_update(
+        &self,
+        progress: &Progress,
+        sending: &Mutex<()>,
+    ) -> anyhow::Result<Option<Progress>> {
+        let _sending = sending.lock().await;
+        if self
+            .bars
+            .lock()
+            .await
+            .get(&progress.id)
+            .is_none_or(|p| p.finishing)
+        {
+            return Ok(None);
+        }
+
+        let notification_id = self.send(progress, 0, None).await?;
+        let mut bars = self.bars.lock().await;
+        Ok(bars.get_mut(&progress.id).map(|progress| {
+            progress.notification_id = notification_id;
+            progress.clone()
+        }))
+    }
+
+    /// Sends `progress`, returning the id of the notification showing it.
+    async fn send(
+        &self,
+        progress: &Progress,
+        timeout: i32,
+        urgency: Option<Urgency>,
+    ) -> anyhow::Result<u32> {
+        let mut builder = self
+            .notify
+            .builder()
+            .await
+            .with_id(progress.notification_id)
+            .with_summary(&progress.summary)
+            .with_body(&progress.body)
+            .with_timeout(timeout)
+            .with_value(progress.value.into())
+            .with_caller(&progress.caller);
+        if let Some(urgency) = urgency {
+            builder = builder.with_urgency(urgency);
+        }
+
+        builder.send().await
+    }
+}
+
+/// Marks `progress` as sent now, so updates arriving while it's on its way
+/// are rate limited, and returns what to send.
+fn start_send(progress: &mut Progress) -> Progress {
+    progress.last_sent = Some(Instant::now());
+    progress.pending = false;
+    progress.clone()
+}
@@ -0,0 +1,76 @@
+use crate::idle::LoginManagerProxy;
+use serde::{Deserialize, Serialize};
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum PowerAction {
+    Suspend,
+    Hibernate,
+    HybridSleep,
+    SuspendThenHibernate,
+    Reboot,
+    PowerOff,
+}
+
+impl PowerAction {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Suspend => "suspend",
+            Self::Hibernate => "hibernate",
+            Self::HybridSleep => "hybrid-sleep",
+            Self::SuspendThenHibernate => "suspend-then-hibernate",
+            Self::Reboot => "reboot",
+            Self::PowerOff => "power-off",
+        }
+    }
+}
+
+/// What logind answers to the `Can*` queries for each action: one of `yes`,
+/// `no`, `challenge` (needs authorization) or `na` (not supported).
+#[derive(Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Capabilities {
+    pub suspend: String,
+    pub hibernate: String,
+    pub hybrid_sleep: String,
+    pub suspend_then_hibernate: String,
+    pub reboot: String,
+    pub power_off: String,
+}
+
+#[derive(Clone)]
+pub struct Power {
+    login_manager: LoginManagerProxy<'static>,
+}
+
+impl Power {
+    pub fn new(login_manager: LoginManagerProxy<'static>) -> Self {
+        Self { login_manager }
+    }
+
+    pub async fn run(&self, action: PowerAction) -> anyhow::Result<()> {
+        match action {
+            PowerAction::Suspend => self.login_manager.suspend(false).await?,
+            PowerAction::Hibernate => self.login_manager.hibernate(false).await?,
+            PowerAction::HybridSleep => self.login_manager.hybrid_sleep(false).await?,
+            PowerAction::SuspendThenHibernate => {
+                self.login_manager.suspend_then_hibernate(false).await?
+            }
+            PowerAction::Reboot => self.login_manager.reboot(false).await?,
+            PowerAction::PowerOff => self.login_manager.power_off(false).await?,
+        }
+
+        Ok(())
+    }
+
+    pub async fn capabilities(&self) -> anyhow::Result<Capabilities> {
+        Ok(Capabilities {
+            suspend: self.login_manager.can_suspend().await?,
+            hibernate: self.login_manager.can_hibernate().await?,
+            hybrid_sleep: self.login_manager.can_hybrid_sleep().await?,
+            suspend_then_hibernate: self.login_manager.can_suspend_then_hibernate().await?,
+            reboot: self.login_manager.can_reboot().await?,
+            power_off: self.login_manager.can_power_off().await?,
+        })
+    }
+}
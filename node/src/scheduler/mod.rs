@@ -0,0 +1,512 @@
+use crate::{auth::Scope, idle::Idle, notify::NotificationManager, power};
+use anyhow::Context;
+use chrono::{DateTime, Datelike, Local, NaiveTime, TimeDelta, Weekday};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    env,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+use tokio::sync::{Mutex, RwLock};
+
+/// The caller scheduled jobs act as.
+const SCHEDULER: &str = "scheduler";
+
+/// One-off jobs that were due longer ago than this when the node starts are
+/// dropped instead of being run late.
+const MISSED_GRACE: TimeDelta = TimeDelta::minutes(5);
+
+const EVERY_DAY: [Weekday; 7] = [
+    Weekday::Mon,
+    Weekday::Tue,
+    Weekday::Wed,
+    Weekday::Thu,
+    Weekday::Fri,
+    Weekday::Sat,
+    Weekday::Sun,
+];
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Action {
+    Lock {
+        session: Option<String>,
+    },
+    Unlock {
+        session: Option<String>,
+    },
+    Power {
+        action: power::PowerAction,
+    },
+    /// Holds an idle inhibitor, for `duration` seconds if given.
+    Inhibit {
+        #[serde(default)]
+        reason: String,
+        duration: Option<u64>,
+    },
+    /// Releases one hold, or every hold scheduled jobs took when no id is
+    /// given.
+    Uninhibit {
+        hold: Option<u64>,
+    },
+    Notify {
+        summary: String,
+        #[serde(default)]
+        body: String,
+    },
+}
+
+impl Action {
+    /// What a key needs, besides `schedule:control`, to schedule this.
+    pub fn scope(&self) -> Scope {
+        match self {
+            Self::Lock { .. } | Self::Unlock { .. } => Scope::IdleControl,
+            Self::Inhibit { .. } | Self::Uninhibit { .. } => Scope::IdleControl,
+            Self::Power { .. } => Scope::PowerControl,
+            Self::Notify { .. } => Scope::NotifySend,
+        }
+    }
+
+    fn describe(&self) -> String {
+        match self {
+            Self::Lock { .. } => "Locking the session".to_string(),
+            Self::Unlock { .. } => "Unlocking the session".to_string(),
+            Self::Power { action } => format!("Running {}", action.as_str()),
+            Self::Inhibit { .. } => "Inhibiting idle".to_string(),
+            Self::Uninhibit { .. } => "Releasing idle inhibitors".to_string(),
+            Self::Notify { summary, .. } => format!("Showing \"{summary}\""),
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Schedule {
+    Once,
+    Weekly {
+        #[serde(with = "hour_minute")]
+        time: NaiveTime,
+        days: Box<[Weekday]>,
+    },
+}
+
+impl Schedule {
+    fn next_after(&self, after: DateTime<Local>) -> Option<DateTime<Local>> {
+        match self {
+            Self::Once => None,
+            Self::Weekly { time, days } => next_occurrence(after, *time, days),
+        }
+    }
+}
+
+/// First local `time` on one of `days` strictly after `after`.
+fn next_occurrence(
+    after: DateTime<Local>,
+    time: NaiveTime,
+    days: &[Weekday],
+) -> Option<DateTime<Local>> {
+    (0..=7)
+        .filter_map(|offset| {
+            let date = after.date_naive() + TimeDelta::days(offset);
+            days.contains(&date.weekday())
+                .then(|| date.and_time(time).and_local_timezone(Local).earliest())
+                .flatten()
+        })
+        .find(|at| *at > after)
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct Job {
+    pub id: u64,
+    pub action: Action,
+    pub schedule: Schedule,
+    pub next_run: DateTime<Local>,
+    /// Seconds before `next_run` to warn the user with a notification.
+    pub warn_before: Option<u64>,
+    /// The caller that scheduled the job. Releasing a hold by id happens on
+    /// their behalf.
+    #[serde(default = "scheduler")]
+    pub owner: String,
+    #[serde(default)]
+    warned: bool,
+}
+
+fn scheduler() -> String {
+    SCHEDULER.to_string()
+}
+
+/// When to run a new job: `in` seconds from now, `at` a local `HH:MM` time
+/// or RFC 3339 timestamp, or `every` week on the given days.
+#[derive(Deserialize)]
+pub struct JobRequest {
+    pub action: Action,
+    #[serde(rename = "in")]
+    pub delay: Option<u64>,
+    pub at: Option<String>,
+    pub every: Option<Every>,
+    pub warn_before: Option<u64>,
+}
+
+#[derive(Deserialize)]
+pub struct Every {
+    pub days: Box<[Weekday]>,
+    pub time: String,
+}
+
+impl JobRequest {
+    /// Works out the schedule and first run time, rejecting requests that
+    /// don't name exactly one of `in`, `at` and `every`, or whose times are
+    /// out of range.
+    pub fn schedule(&self) -> anyhow::Result<(Schedule, DateTime<Local>)> {
+        let (schedule, next_run) = self.first_run()?;
+        if let Some(warn_before) = self.warn_before {
+            seconds(warn_before)
+                .and_then(|warn| next_run.checked_sub_signed(warn))
+                .ok_or_else(|| anyhow::anyhow!("warn_before out of range"))?;
+        }
+
+        Ok((schedule, next_run))
+    }
+
+    fn first_run(&self) -> anyhow::Result<(Schedule, DateTime<Local>)> {
+        let now = Local::now();
+        match (self.delay, &self.at, &self.every) {
+            (Some(delay), None, None) => {
+                let next_run = seconds(delay)
+                    .and_then(|delay| now.checked_add_signed(delay))
+                    .ok_or_else(|| anyhow::anyhow!("Delay out of range"))?;
+                Ok((Schedule::Once, next_run))
+            }
+            (None, Some(at), None) => {
+                if let Ok(at) = DateTime::parse_from_rfc3339(at) {
+                    return Ok((Schedule::Once, at.with_timezone(&Local)));
+                }
+                let time = NaiveTime::parse_from_str(at, "%H:%M")?;
+                let next_run = next_occurrence(now, time, &EVERY_DAY)
+                    .ok_or_else(|| anyhow::anyhow!("{at} does not exist in the coming days"))?;
+                Ok((Schedule::Once, next_run))
+            }
+            (None, None, Some(every)) => {
+                let schedule = Schedule::Weekly {
+                    time: NaiveTime::parse_from_str(&every.time, "%H:%M")?,
+                    days: every.days.clone(),
+                };
+                let next_run = schedule
+                    .next_after(now)
+                    .ok_or_else(|| anyhow::anyhow!("No upcoming day to run on"))?;
+                Ok((schedule, next_run))
+            }
+            _ => Err(anyhow::anyhow!(
+                "Exactly one of \"in\", \"at\" and \"every\" is required"
+            )),
+        }
+    }
+}
+
+fn seconds(secs: u64) -> Option<TimeDelta> {
+    TimeDelta::try_seconds(secs.try_into().ok()?)
+}
+
+mod hour_minute {
+    use chrono::NaiveTime;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(time: &NaiveTime, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&time.format("%H:%M").to_string())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<NaiveTime, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        NaiveTime::parse_from_str(&s, "%H:%M").map_err(serde::de::Error::custom)
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+struct Jobs {
+    next_id: u64,
+    jobs: BTreeMap<u64, Job>,
+}
+
+impl Default for Jobs {
+    fn default() -> Self {
+        Self {
+            next_id: 1,
+            jobs: BTreeMap::new(),
+        }
+    }
+}
+
+pub fn default_path() -> PathBuf {
+    env::var_os("XDG_STATE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/state")))
+        .unwrap_or_default()
+        .join("moxapi/schedule.json")
+}
+
+/// Runs delayed and recurring actions, persisting jobs to disk so they
+/// survive restarts.
+#[derive(Clone)]
+pub struct Scheduler {
+    path: PathBuf,
+    jobs: Arc<Mutex<Jobs>>,
+    idle: Arc<RwLock<Idle>>,
+    power: power::Power,
+    notify: NotificationManager,
+}
+
+impl Scheduler {
+    /// Loads the jobs saved at `path`. A file that can't be read is moved
+    /// aside, so the node still starts and new jobs don't overwrite it.
+    pub fn new(
+        path: PathBuf,
+        idle: Arc<RwLock<Idle>>,
+        power: power::Power,
+        notify: NotificationManager,
+    ) -> Self {
+        let mut jobs = load(&path).unwrap_or_else(|e| {
+            let aside = path.with_extension("json.bad");
+            match std::fs::rename(&path, &aside) {
+                Ok(()) => log::error!(
+                    "Starting without scheduled jobs, moved {} to {}: {e:#}",
+                    path.display(),
+                    aside.display()
+                ),
+                Err(_) => log::error!("Starting without scheduled jobs: {e:#}"),
+            }
+            Jobs::default()
+        });
+
+        let now = Local::now();
+        jobs.jobs.retain(|id, job| {
+            if job
+                .next_run
+                .checked_add_signed(MISSED_GRACE)
+                .is_none_or(|latest| latest >= now)
+            {
+                return true;
+            }
+            match job.schedule.next_after(now) {
+                Some(next_run) => {
+                    job.next_run = next_run;
+                    job.warned = false;
+                    true
+                }
+                None => {
+                    log::warn!("Dropping scheduled job {id}, missed at {}", job.next_run);
+                    false
+                }
+            }
+        });
+
+        Self {
+            path,
+            jobs: Arc::new(Mutex::new(jobs)),
+            idle,
+            power,
+            notify,
+        }
+    }
+
+    pub async fn list(&self) -> Box<[Job]> {
+        self.jobs.lock().await.jobs.values().cloned().collect()
+    }
+
+    pub async fn add(
+        &self,
+        action: Action,
+        schedule: Schedule,
+        next_run: DateTime<Local>,
+        warn_before: Option<u64>,
+        owner: String,
+    ) -> anyhow::Result<Job> {
+        let mut jobs = self.jobs.lock().await;
+        let job = Job {
+            id: jobs.next_id,
+            action,
+            schedule,
+            next_run,
+            warn_before,
+            owner,
+            warned: false,
+        };
+        jobs.next_id += 1;
+        jobs.jobs.insert(job.id, job.clone());
+        if let Err(e) = save(&self.path, &jobs) {
+            jobs.jobs.remove(&job.id);
+            return Err(e);
+        }
+
+        Ok(job)
+    }
+
+    /// Returns `false` if there is no such job.
+    pub async fn cancel(&self, id: u64) -> anyhow::Result<bool> {
+        let mut jobs = self.jobs.lock().await;
+        let Some(job) = jobs.jobs.remove(&id) else {
+            return Ok(false);
+        };
+        if let Err(e) = save(&self.path, &jobs) {
+            jobs.jobs.insert(id, job);
+            return Err(e);
+        }
+
+        Ok(true)
+    }
+
+    pub async fn run(self) {
+        let mut interval = tokio::time::interval(Duration::from_secs(1));
+        loop {
+            interval.tick().await;
+
+            let (warnings, due) = self.take_due().await;
+            for job in warnings {
+                if let Err(e) = self.warn(&job).await {
+                    log::warn!("Failed to warn about scheduled job {}: {e}", job.id);
+                }
+            }
+            for job in due {
+                if let Err(e) = self.execute(&job).await {
+                    log::warn!("Scheduled job {} failed: {e}", job.id);
+                }
+            }
+        }
+    }
+
+    /// Collects the jobs to warn about and to run now, advancing recurring
+    /// jobs and dropping finished ones before anything is executed.
+    async fn take_due(&self) -> (Vec<Job>, Vec<Job>) {
+        let now = Local::now();
+        let mut jobs = self.jobs.lock().await;
+        let mut warnings = Vec::new();
+        let mut due = Vec::new();
+
+        for job in jobs.jobs.values_mut() {
+            if now >= job.next_run {
+                due.push(job.clone());
+                continue;
+            }
+
+            // A warning too early to represent is due already.
+            let warn = job.warn_before.is_some_and(|secs| {
+                seconds(secs)
+                    .and_then(|warn| job.next_run.checked_sub_signed(warn))
+                    .is_none_or(|warn_at| now >= warn_at)
+            });
+            if !job.warned && warn {
+                job.warned = true;
+                warnings.push(job.clone());
+            }
+        }
+
+        for job in &due {
+            match job.schedule.next_after(now) {
+                Some(next_run) => {
+                    if let Some(next) = jobs.jobs.get_mut(&job.id) {
+                        next.next_run = next_run;
+                        next.warned = false;
+                    }
+                }
+                None => {
+                    jobs.jobs.remove(&job.id);
+                }
+            }
+        }
+
+        if !(warnings.is_empty() && due.is_empty())
+            && let Err(e) = save(&self.path, &jobs)
+        {
+            log::warn!("Failed to save schedule: {e}");
+        }
+
+        (warnings, due)
+    }
+
+    async fn warn(&self, job: &Job) -> anyhow::Result<()> {
+        let remaining = (job.next_run - Local::now()).num_seconds().max(0);
+        let body = match remaining {
+            0..60 => format!("{} in {remaining} seconds", job.action.describe()),
+            _ => format!(
+                "{} in {} minutes",
+                job.action.describe(),
+                (remaining + 59) / 60
+            ),
+        };
+
+        self.notify
+            .builder()
+            .await
+            .with_summary("Scheduled action")
+            .with_body(&body)
+            .with_caller(SCHEDULER)
+            .send()
+            .await?;
+
+        Ok(())
+    }
+
+    async fn execute(&self, job: &Job) -> anyhow::Result<()> {
+        match &job.action {
+            Action::Lock { session } => self.idle.read().await.lock(session.as_deref()).await,
+            Action::Unlock { session } => self.idle.read().await.unlock(session.as_deref()).await,
+            Action::Power { action } => self.power.run(*action).await,
+            Action::Inhibit { reason, duration } => self
+                .idle
+                .write()
+                .await
+                .inhibit(
+                    SCHEDULER,
+                    Some(&format!("schedule-{}", job.id)),
+                    reason,
+                    duration.map(Duration::from_secs),
+                )
+                .await
+                .map(drop),
+            Action::Uninhibit { hold: Some(id) } => self
+                .idle
+                .write()
+                .await
+                .uninhibit(&job.owner, *id)
+                .await
+                .map(drop),
+            Action::Uninhibit { hold: None } => {
+                self.idle.write().await.uninhibit_owned(SCHEDULER).await
+            }
+            Action::Notify { summary, body } => self
+                .notify
+                .builder()
+                .await
+                .with_summary(summary)
+                .with_body(body)
+                .with_caller(SCHEDULER)
+                .send()
+                .await
+                .map(drop),
+        }
+    }
+}
+
+fn load(path: &Path) -> anyhow::Result<Jobs> {
+    match std::fs::read_to_string(path) {
+        Ok(content) => serde_json::from_str(&content)
+            .with_context(|| format!("{} is not a valid schedule", path.display())),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Jobs::default()),
+        Err(e) => Err(e).with_context(|| format!("Failed to read {}", path.display())),
+    }
+}
+
+/// Writes the job list next to its final location first, so a crash never
+/// leaves a truncated file behind.
+fn save(path: &Path, jobs: &Jobs) -> anyhow::Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    let tmp = path.with_extension("json.tmp");
+    std::fs::write(&tmp, serde_json::to_vec_pretty(jobs)?)?;
+    std::fs::rename(tmp, path)?;
+
+    Ok(())
+}
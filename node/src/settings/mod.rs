@@ -0,0 +1,133 @@
+use crate::socket::PeerRule;
+use anyhow::Context;
+use serde::{Deserialize, Serialize, Serializer};
+use std::{
+    env,
+    net::{IpAddr, Ipv4Addr},
+    path::{Path, PathBuf},
+};
+
+/// Everything the node can be configured with. Each value comes from the
+/// first of these that sets it: command line options, `MOXAPI_*` environment
+/// variables (`MOXAPI_TLS__CERT` for nested ones), the legacy `AUTH_KEY*`
+/// variables, the config file, and the defaults here.
+#[derive(Deserialize, Serialize, Debug)]
+#[serde(default)]
+pub struct Settings {
+    /// Addresses to listen on. `::` takes IPv4 connections too. Empty to
+    /// only use `socket`.
+    pub listen: Vec<IpAddr>,
+    pub port: u16,
+    pub workers: usize,
+    /// Origins browsers may call the API from. Empty allows any.
+    pub cors_origins: Vec<String>,
+    /// Largest request body accepted, in bytes.
+    pub payload_limit: usize,
+    /// Largest JSON request body accepted, in bytes.
+    pub json_limit: usize,
+    /// Act on the graphical session of this user (name or uid) by default.
+    pub user: Option<String>,
+    /// Only consider sessions on this seat.
+    pub seat: Option<String>,
+    pub schedule_file: Option<PathBuf>,
+    /// Named keys with scopes, used instead of `auth_key_file` and `auth_key`.
+    pub auth_keys_file: Option<PathBuf>,
+    pub auth_key_file: Option<PathBuf>,
+    #[serde(serialize_with = "redact")]
+    pub auth_key: Option<String>,
+    pub require_signed: bool,
+    pub tls: Option<Tls>,
+    /// Unix socket to listen on as well, e.g. `$XDG_RUNTIME_DIR/moxapi.sock`.
+    pub socket: Option<PathBuf>,
+    /// Octal permissions of the socket. Peers need write access to connect,
+    /// so rules for other users need a mode that lets them in.
+    pub socket_mode: String,
+    /// Local users that may use the socket without a key. Empty lets the
+    /// user the node runs as do anything.
+    pub socket_peers: Vec<PeerRule>,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct Tls {
+    /// PEM certificate chain, reloaded on SIGHUP.
+    pub cert: PathBuf,
+    pub key: PathBuf,
+    /// Accept client certificates signed by this PEM CA.
+    pub client_ca: Option<PathBuf>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            listen: vec![IpAddr::V4(Ipv4Addr::UNSPECIFIED)],
+            port: 8000,
+            workers: 2,
+            cors_origins: Vec::new(),
+            payload_limit: 1024 * 1024,
+            json_limit: 1024 * 1024,
+            user: None,
+            seat: None,
+            schedule_file: None,
+            auth_keys_file: None,
+            auth_key_file: None,
+            auth_key: None,
+            require_signed: false,
+            tls: None,
+            socket: None,
+            socket_mode: "0660".to_string(),
+            socket_peers: Vec::new(),
+        }
+    }
+}
+
+fn redact<S: Serializer>(value: &Option<String>, serializer: S) -> Result<S::Ok, S::Error> {
+    value.as_ref().map(|_| "********").serialize(serializer)
+}
+
+pub fn default_path() -> PathBuf {
+    env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+        .unwrap_or_default()
+        .join("mox/moxapi/config.yaml")
+}
+
+impl Settings {
+    /// Layers the settings from `file`, or the default config file if it
+    /// exists, the environment, and `overrides` from the command line.
+    pub fn load(
+        file: Option<&Path>,
+        overrides: Vec<(&str, config::Value)>,
+    ) -> anyhow::Result<Self> {
+        let file = match file {
+            Some(file) => config::File::from(file).required(true),
+            None => config::File::from(default_path()).required(false),
+        };
+
+        let legacy = ["AUTH_KEY", "AUTH_KEY_FILE", "AUTH_KEYS_FILE"]
+            .into_iter()
+            .filter_map(|var| Some((var.to_string(), env::var(var).ok()?)))
+            .collect();
+
+        let mut builder = config::Config::builder()
+            .add_source(file)
+            .add_source(config::Environment::default().source(Some(legacy)))
+            .add_source(
+                config::Environment::with_prefix("MOXAPI")
+                    .prefix_separator("_")
+                    .separator("__")
+                    .try_parsing(true)
+                    .list_separator(",")
+                    .with_list_parse_key("listen")
+                    .with_list_parse_key("cors_origins"),
+            );
+        for (key, value) in overrides {
+            builder = builder.set_override(key, value)?;
+        }
+
+        builder
+            .build()
+            .and_then(config::Config::try_deserialize)
+            .context("Invalid config")
+    }
+}
@@ -0,0 +1,121 @@
+use crate::auth::{Key, Scope};
+use serde::{Deserialize, Serialize};
+use std::{
+    any::Any,
+    io,
+    os::unix::{
+        fs::{FileTypeExt, MetadataExt, PermissionsExt},
+        net::{UnixListener, UnixStream},
+    },
+    path::Path,
+};
+
+/// The process on the other end of a Unix socket connection, as the kernel
+/// reports it.
+#[derive(Clone, Copy, Debug)]
+pub struct Peer {
+    pub uid: u32,
+    pub gid: u32,
+}
+
+/// Records who connected over the Unix socket, for
+/// `HttpServer::on_connect`.
+pub fn on_connect(conn: &dyn Any, ext: &mut actix_web::dev::Extensions) {
+    let Some(stream) = conn.downcast_ref::<actix_web::rt::net::UnixStream>() else {
+        return;
+    };
+
+    match stream.peer_cred() {
+        Ok(cred) => {
+            ext.insert(Peer {
+                uid: cred.uid(),
+                gid: cred.gid(),
+            });
+        }
+        Err(e) => log::warn!("Failed to get the credentials of a socket peer: {e}"),
+    }
+}
+
+/// Which local users may use the socket without a key, and for what.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct PeerRule {
+    pub uid: Option<u32>,
+    /// Matched against the peer's primary group only.
+    pub gid: Option<u32>,
+    pub scopes: Vec<Scope>,
+}
+
+impl PeerRule {
+    fn matches(&self, peer: &Peer) -> bool {
+        self.uid.is_none_or(|uid| uid == peer.uid) && self.gid.is_none_or(|gid| gid == peer.gid)
+    }
+}
+
+#[derive(Default)]
+pub struct Policy {
+    rules: Vec<PeerRule>,
+}
+
+impl Policy {
+    /// Uses `rules`, or gives `owner` every scope if there are none.
+    pub fn new(rules: &[PeerRule], owner: u32) -> Self {
+        let rules = if rules.is_empty() {
+            vec![PeerRule {
+                uid: Some(owner),
+                gid: None,
+                scopes: Scope::ALL.to_vec(),
+            }]
+        } else {
+            rules.to_vec()
+        };
+
+        Self { rules }
+    }
+
+    /// The key a peer acts as under the first rule matching it.
+    pub fn key_for(&self, peer: &Peer) -> Option<Key> {
+        let rule = self.rules.iter().find(|rule| rule.matches(peer))?;
+        Some(Key::local(format!("uid {}", peer.uid), rule.scopes.clone()))
+    }
+}
+
+/// Binds `path` with permissions `mode`, replacing a socket left behind by
+/// an earlier run, and returns the listener with the uid that owns it.
+/// Fails if another instance is still listening there.
+pub fn bind(path: &Path, mode: u32) -> io::Result<(UnixListener, u32)> {
+    if std::fs::symlink_metadata(path).is_ok_and(|meta| meta.file_type().is_socket()) {
+        match UnixStream::connect(path) {
+            Ok(_) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::AddrInUse,
+                    format!("Something is already listening on {}", path.display()),
+                ));
+            }
+            Err(e) if e.kind() == io::ErrorKind::ConnectionRefused => std::fs::remove_file(path)?,
+            Err(e) => return Err(e),
+        }
+    }
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+
+    let listener = UnixListener::bind(path)?;
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))?;
+    let owner = std::fs::metadata(path)?.uid();
+    Ok((listener, owner))
+}
+
+/// Removes the socket `bind` created, once the server has stopped.
+pub fn unbind(path: &Path) {
+    if let Err(e) = std::fs::remove_file(path) {
+        log::warn!("Failed to remove {}: {e}", path.display());
+    }
+}
+
+/// Parses an octal file mode such as `0660`.
+pub fn parse_mode(mode: &str) -> Option<u32> {
+    let digits = mode.trim_start_matches("0o");
+    u32::from_str_radix(digits, 8)
+        .ok()
+        .filter(|mode| *mode <= 0o777)
+}
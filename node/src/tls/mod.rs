@@ -0,0 +1,117 @@
+use anyhow::Context;
+use rustls::{
+    RootCertStore, ServerConfig,
+    crypto::ring,
+    pki_types::{CertificateDer, PrivateKeyDer, pem::PemObject},
+    server::{ClientHello, ResolvesServerCert, WebPkiClientVerifier},
+    sign::CertifiedKey,
+};
+use sha2::{Digest, Sha256};
+use std::{
+    any::Any,
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+};
+
+/// The certificate a client authenticated with, kept with its connection.
+#[derive(Clone)]
+pub struct ClientCertificate(CertificateDer<'static>);
+
+impl ClientCertificate {
+    /// The hex SHA-256 of the certificate, as keyset entries name it.
+    pub fn fingerprint(&self) -> String {
+        hex::encode(Sha256::digest(&self.0))
+    }
+}
+
+/// Records the client certificate of a TLS connection, for
+/// `HttpServer::on_connect`.
+pub fn on_connect(conn: &dyn Any, ext: &mut actix_web::dev::Extensions) {
+    let Some(tls) = conn
+        .downcast_ref::<actix_tls::accept::rustls_0_23::TlsStream<actix_web::rt::net::TcpStream>>()
+    else {
+        return;
+    };
+
+    if let Some(cert) = tls.get_ref().1.peer_certificates().and_then(<[_]>::first) {
+        ext.insert(ClientCertificate(cert.clone()));
+    }
+}
+
+/// The server certificate, which can be swapped for a renewed one without
+/// restarting.
+#[derive(Debug)]
+pub struct Certificates {
+    cert: PathBuf,
+    key: PathBuf,
+    current: RwLock<Arc<CertifiedKey>>,
+}
+
+impl Certificates {
+    pub fn load(cert: &Path, key: &Path) -> anyhow::Result<Self> {
+        Ok(Self {
+            cert: cert.to_path_buf(),
+            key: key.to_path_buf(),
+            current: RwLock::new(Arc::new(certified_key(cert, key)?)),
+        })
+    }
+
+    /// Reads the certificate and key files again, keeping the current ones
+    /// if they're invalid.
+    pub fn reload(&self) -> anyhow::Result<()> {
+        let certified = certified_key(&self.cert, &self.key)?;
+        *self.current.write().unwrap() = Arc::new(certified);
+        Ok(())
+    }
+}
+
+impl ResolvesServerCert for Certificates {
+    fn resolve(&self, _client_hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        Some(Arc::clone(&self.current.read().unwrap()))
+    }
+}
+
+fn certified_key(cert: &Path, key: &Path) -> anyhow::Result<CertifiedKey> {
+    let chain = CertificateDer::pem_file_iter(cert)
+        .and_then(Iterator::collect::<Result<Vec<_>, _>>)
+        .with_context(|| format!("Failed to read certificates from {}", cert.display()))?;
+    if chain.is_empty() {
+        anyhow::bail!("No certificates in {}", cert.display());
+    }
+    let key = PrivateKeyDer::from_pem_file(key)
+        .with_context(|| format!("Failed to read the private key from {}", key.display()))?;
+
+    Ok(CertifiedKey::new(
+        chain,
+        ring::sign::any_supported_type(&key)?,
+    ))
+}
+
+/// Serves `certificates`, asking clients for a certificate signed by
+/// `client_ca` if given. Clients without one can still use an API key.
+pub fn server_config(
+    certificates: Arc<Certificates>,
+    client_ca: Option<&Path>,
+) -> anyhow::Result<ServerConfig> {
+    let provider = Arc::new(ring::default_provider());
+    let builder = ServerConfig::builder_with_provider(provider.clone())
+        .with_safe_default_protocol_versions()?;
+
+    let builder = match client_ca {
+        Some(client_ca) => {
+            let mut roots = RootCertStore::empty();
+            for cert in CertificateDer::pem_file_iter(client_ca)
+                .with_context(|| format!("Failed to read {}", client_ca.display()))?
+            {
+                roots.add(cert?)?;
+            }
+            let verifier = WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider)
+                .allow_unauthenticated()
+                .build()?;
+            builder.with_client_cert_verifier(verifier)
+        }
+        None => builder.with_no_client_auth(),
+    };
+
+    Ok(builder.with_cert_resolver(certificates))
+}